@@ -0,0 +1,225 @@
+//! Canonical URLs so the same page isn't bookmarked twice under slightly different URLs. The canonical form has:
+//!
+//!   - A lowercase scheme and host (without a trailing dot)
+//!   - No default port (80 for http, 443 for https)
+//!   - No trailing slash, except for the root path which is always "/"
+//!   - The query parameters sorted, without empty or tracking parameters (e.g. utm_source)
+//!
+//! The user info, path case and fragment are kept since they can change which page is shown.
+
+use std::ops::Range;
+
+use crate::settings::UrlPolicy;
+
+/// Byte ranges of the parts of a URL, so the parts can be highlighted as well as changed
+struct UrlParts {
+    scheme: Range<usize>,
+    user_info: Option<Range<usize>>,
+    host: Range<usize>,
+    /// Empty without a port
+    port: Range<usize>,
+    path: Range<usize>,
+    /// Without the ?. Empty without a query
+    query: Range<usize>,
+    /// Without the #
+    fragment: Option<Range<usize>>,
+}
+
+/// Split the URL into its parts, or None if it has no scheme
+fn parse(url: &str) -> Option<UrlParts> {
+    let scheme = 0..url.find("://")?;
+    let start = scheme.end + 3;
+
+    let fragment_start = url[start..].find('#').map(|hash| start + hash);
+    let end = fragment_start.unwrap_or(url.len());
+    let query_start = url[start..end].find('?').map(|question| start + question);
+    let end = query_start.unwrap_or(end);
+    let authority_end = url[start..end].find('/').map_or(end, |slash| start + slash);
+
+    let authority = &url[start..authority_end];
+    let (user_info, host_start) = match authority.rfind('@') {
+        Some(at) => (Some(start..start + at), start + at + 1),
+        None => (None, start),
+    };
+    // Digits after the last colon is a port. This also works for IPv6 hosts since they end with ']'
+    let (host, port) = match url[host_start..authority_end].rfind(':') {
+        Some(colon)
+            if url[host_start + colon + 1..authority_end]
+                .chars()
+                .all(|c| c.is_ascii_digit()) =>
+        {
+            (
+                host_start..host_start + colon,
+                host_start + colon + 1..authority_end,
+            )
+        }
+        _ => (host_start..authority_end, authority_end..authority_end),
+    };
+
+    Some(UrlParts {
+        scheme,
+        user_info,
+        host,
+        port,
+        path: authority_end..end,
+        query: query_start.map_or(end..end, |question| {
+            question + 1..fragment_start.unwrap_or(url.len())
+        }),
+        fragment: fragment_start.map(|hash| hash + 1..url.len()),
+    })
+}
+
+/// Byte range of the host in the URL (without any user info or port)
+pub fn host_range(url: &str) -> Option<Range<usize>> {
+    parse(url).map(|parts| parts.host)
+}
+
+pub fn canonicalize(url: &str, policy: &UrlPolicy) -> String {
+    let url = url.trim();
+    // Not something that can be canonicalized so leave it alone
+    let Some(parts) = parse(url) else {
+        return url.to_string();
+    };
+    let scheme = url[parts.scheme].to_ascii_lowercase();
+    let port = &url[parts.port];
+    let is_default_port = matches!(
+        (scheme.as_str(), port),
+        (_, "") | ("http", "80") | ("https", "443")
+    );
+
+    let path = match url[parts.path].trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    };
+
+    let mut params = url[parts.query]
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .filter(|param| !is_tracking_param(param, policy))
+        .collect::<Vec<&str>>();
+    params.sort_unstable();
+
+    let mut canonical = format!("{scheme}://");
+    if let Some(user_info) = parts.user_info {
+        canonical.push_str(format!("{}@", &url[user_info]).as_str());
+    }
+    canonical.push_str(
+        url[parts.host]
+            .trim_end_matches('.')
+            .to_lowercase()
+            .as_str(),
+    );
+    if !is_default_port {
+        canonical.push_str(format!(":{port}").as_str());
+    }
+    canonical.push_str(path);
+    if !params.is_empty() {
+        canonical.push_str(format!("?{}", params.join("&")).as_str());
+    }
+    if let Some(fragment) = parts.fragment {
+        canonical.push_str(format!("#{}", &url[fragment]).as_str());
+    }
+
+    canonical
+}
+
+/// URLs with the same key are the same bookmark. http and https are treated the same since most sites serve both.
+pub fn duplicate_key(url: &str, policy: &UrlPolicy) -> String {
+    let canonical = canonicalize(url, policy);
+    match canonical.strip_prefix("http://") {
+        Some(rest) => format!("https://{rest}"),
+        None => canonical,
+    }
+}
+
+/// A tracking parameter in the policy matches the name case insensitively. One ending with * matches as a prefix.
+fn is_tracking_param(param: &str, policy: &UrlPolicy) -> bool {
+    let name = param.split('=').next().unwrap_or_default().to_lowercase();
+
+    policy.tracking_params.iter().any(|tracking| {
+        let tracking = tracking.to_lowercase();
+        match tracking.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == tracking,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::canonical_url::{canonicalize, duplicate_key, host_range};
+    use crate::settings::UrlPolicy;
+
+    fn canonical(url: &str) -> String {
+        canonicalize(url, &UrlPolicy::default())
+    }
+
+    #[test]
+    fn host_port_and_path() {
+        assert_eq!(canonical("HTTPS://Example.COM."), "https://example.com/");
+        assert_eq!(
+            canonical("https://example.com:443/Docs/"),
+            "https://example.com/Docs"
+        );
+        assert_eq!(canonical("http://example.com:80"), "http://example.com/");
+        assert_eq!(
+            canonical("http://example.com:443/"),
+            "http://example.com:443/"
+        );
+        assert_eq!(
+            canonical("https://User@[::1]:8080//a/#Top"),
+            "https://User@[::1]:8080//a#Top"
+        );
+    }
+
+    #[test]
+    fn host() {
+        let url = "https://user:pw@example.com:8080/path?q=1";
+        assert_eq!(&url[host_range(url).unwrap()], "example.com");
+        let url = "http://[::1]/x";
+        assert_eq!(&url[host_range(url).unwrap()], "[::1]");
+        let url = "http://[::1]:8080?q=a/b";
+        assert_eq!(&url[host_range(url).unwrap()], "[::1]");
+        assert_eq!(host_range("not a url"), None);
+    }
+
+    #[test]
+    fn query() {
+        assert_eq!(
+            canonical("https://example.com/?utm_source=x&UTM_Medium=y&fbclid=z"),
+            "https://example.com/"
+        );
+        assert_eq!(
+            canonical("https://example.com/search?q=rust&&page=2&gclid=1#results"),
+            "https://example.com/search?page=2&q=rust#results"
+        );
+
+        let policy = UrlPolicy {
+            tracking_params: vec![String::from("ref"), String::from("source_*")],
+        };
+        assert_eq!(
+            canonicalize("https://a.com?ref=x&source_id=1&utm_source=y", &policy),
+            "https://a.com/?utm_source=y"
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        let policy = UrlPolicy::default();
+        let key = duplicate_key("https://example.com/", &policy);
+
+        for url in [
+            "http://example.com",
+            "https://EXAMPLE.com",
+            "https://example.com/?utm_source=x",
+        ] {
+            assert_eq!(duplicate_key(url, &policy), key, "{url}");
+        }
+        assert_ne!(duplicate_key("https://example.com/a", &policy), key);
+    }
+
+    #[test]
+    fn not_a_url() {
+        assert_eq!(canonical(" not a url "), "not a url");
+    }
+}
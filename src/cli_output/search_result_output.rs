@@ -1,4 +1,9 @@
 use ansi_term::Colour::Blue;
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::OutputFormat;
+use crate::csv::{Line, Schema, format_line};
 
 pub enum TextPart {
     MatchedText(String),
@@ -24,6 +29,27 @@ impl TextPart {
         parts.iter().map(|part| part.len()).sum()
     }
 
+    /// The text without any highlighting
+    pub fn plain_string(parts: &[TextPart]) -> String {
+        parts.iter().map(|part| part.text().as_str()).collect()
+    }
+
+    /// Start (inclusive) and end (exclusive) character offsets of the matched text
+    pub fn match_spans(parts: &[TextPart]) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        let mut start = 0;
+
+        for part in parts {
+            let end = start + part.len();
+            if let TextPart::MatchedText(_) = part {
+                spans.push((start, end));
+            }
+            start = end;
+        }
+
+        spans
+    }
+
     /// Highlighted the matched text
     pub fn pretty_string(parts: &[TextPart]) -> String {
         let mut out = String::new();
@@ -91,6 +117,36 @@ impl MatchedBookmark {
     }
 }
 
+/// Machine-readable bookmark. This schema is documented in the README so don't change it lightly.
+#[derive(Serialize)]
+struct JsonBookmark<'a> {
+    url: String,
+    description: String,
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matches: Option<JsonMatches>,
+}
+
+#[derive(Serialize)]
+struct JsonMatches {
+    url: Vec<(usize, usize)>,
+    description: Vec<(usize, usize)>,
+}
+
+impl<'a> JsonBookmark<'a> {
+    fn new(bookmark: &'a MatchedBookmark, spans: bool) -> Self {
+        Self {
+            url: TextPart::plain_string(&bookmark.url),
+            description: TextPart::plain_string(&bookmark.description),
+            tags: &bookmark.tags,
+            matches: spans.then(|| JsonMatches {
+                url: TextPart::match_spans(&bookmark.url),
+                description: TextPart::match_spans(&bookmark.description),
+            }),
+        }
+    }
+}
+
 pub struct SearchResultOutput {
     url_max: usize,
     desc_max: usize,
@@ -121,10 +177,21 @@ impl SearchResultOutput {
     }
 
     pub fn print(&self) {
-        for line in &self.lines {
+        self.print_with_prefix(|_| String::new());
+    }
+
+    /// Print with each bookmark numbered (starting at 1) so the user can pick one
+    pub fn print_numbered(&self) {
+        let width = self.lines.len().to_string().len();
+        self.print_with_prefix(|index| format!("{:>width$}) ", index + 1));
+    }
+
+    fn print_with_prefix(&self, prefix: impl Fn(usize) -> String) {
+        for (index, line) in self.lines.iter().enumerate() {
             // Can't use println formatting width because gets messed up by colored lines
             println!(
-                "{}{} {}{} {}",
+                "{}{}{} {}{} {}",
+                prefix(index),
                 line.url_pretty_string(),
                 generate_padding(line.url_len(), self.url_max),
                 line.description_pretty_string(),
@@ -135,6 +202,73 @@ impl SearchResultOutput {
     }
 }
 
+impl SearchResultOutput {
+    /// Print in the given format. Spans (i.e. the matched text) can only be included in JSON formats.
+    pub fn print_as(&self, format: OutputFormat, spans: bool) -> Result<()> {
+        match format {
+            OutputFormat::Table => self.print(),
+            OutputFormat::Json => {
+                let bookmarks = self
+                    .lines
+                    .iter()
+                    .map(|line| JsonBookmark::new(line, spans))
+                    .collect::<Vec<JsonBookmark>>();
+                println!("{}", serde_json::to_string_pretty(&bookmarks)?);
+            }
+            OutputFormat::Jsonl => {
+                for line in &self.lines {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&JsonBookmark::new(line, spans))?
+                    );
+                }
+            }
+            OutputFormat::Tsv => {
+                for line in &self.lines {
+                    println!(
+                        "{}\t{}\t{}",
+                        escape_tsv(&TextPart::plain_string(&line.url)),
+                        escape_tsv(&TextPart::plain_string(&line.description)),
+                        line.tags
+                            .iter()
+                            .map(|tag| escape_tsv_tag(tag))
+                            .collect::<Vec<String>>()
+                            .join(",")
+                    );
+                }
+            }
+            OutputFormat::Csv => {
+                let schema = Schema::required();
+                for line in &self.lines {
+                    let line = Line {
+                        url: TextPart::plain_string(&line.url),
+                        description: TextPart::plain_string(&line.description),
+                        tags: line.tags.clone(),
+                        ..Default::default()
+                    };
+                    println!("{}", format_line(&schema, &line));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Tabs and new lines would break the TSV so they are backslash escaped (as is the backslash itself)
+fn escape_tsv(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Tags are comma separated, so a comma in a tag is also escaped
+fn escape_tsv_tag(tag: &str) -> String {
+    escape_tsv(tag).replace(',', "\\,")
+}
+
 // Inspiration: https://docs.rs/crate/tabwriter/1.2.1/source/src/lib.rs
 fn generate_padding(current_len: usize, pad_to: usize) -> String {
     " ".repeat(pad_to - current_len)
@@ -142,10 +276,52 @@ fn generate_padding(current_len: usize, pad_to: usize) -> String {
 
 #[cfg(test)]
 mod tests {
-    use crate::cli_output::search_result_output::generate_padding;
+    use crate::cli_output::search_result_output::{
+        JsonBookmark, MatchedBookmark, TextPart, escape_tsv, escape_tsv_tag, generate_padding,
+    };
 
     #[test]
     fn test_generate_padding() {
         assert_eq!(generate_padding(7, 10), "   ");
     }
+
+    #[test]
+    fn test_match_spans() {
+        let parts = vec![
+            TextPart::Text(String::from("Süper ")),
+            TextPart::MatchedText(String::from("search")),
+            TextPart::Text(String::from(" and ")),
+            TextPart::MatchedText(String::from("Search")),
+        ];
+
+        assert_eq!(TextPart::match_spans(&parts), vec![(6, 12), (17, 23)]);
+        assert_eq!(TextPart::plain_string(&parts), "Süper search and Search");
+    }
+
+    #[test]
+    fn test_json_bookmark() {
+        let bookmark = MatchedBookmark::new(
+            vec![TextPart::Text(String::from("https://google.com"))],
+            vec![
+                TextPart::MatchedText(String::from("Google")),
+                TextPart::Text(String::from(" search")),
+            ],
+            vec![String::from("b"), String::from("A")],
+        );
+
+        assert_eq!(
+            serde_json::to_string(&JsonBookmark::new(&bookmark, false)).unwrap(),
+            r#"{"url":"https://google.com","description":"Google search","tags":["A","b"]}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&JsonBookmark::new(&bookmark, true)).unwrap(),
+            r#"{"url":"https://google.com","description":"Google search","tags":["A","b"],"matches":{"url":[],"description":[[0,6]]}}"#
+        );
+    }
+
+    #[test]
+    fn test_escape_tsv() {
+        assert_eq!(escape_tsv("a\tb\nc\\"), "a\\tb\\nc\\\\");
+        assert_eq!(escape_tsv_tag("a,b\\"), "a\\,b\\\\");
+    }
 }
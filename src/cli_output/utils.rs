@@ -1,4 +1,6 @@
 use ansi_term::Colour::{Green, Red, Yellow};
+use anyhow::{Context, Result};
+use std::io::{self, Write};
 use std::process;
 
 pub fn print_success(msg: &str) {
@@ -9,7 +11,33 @@ pub fn print_warning(msg: &str) {
     println!("{}", Yellow.paint(msg));
 }
 
-pub fn exit_error(msg: &str) {
+/// Warn on stderr so it isn't mixed in with output meant for other programs (e.g. search --format json)
+pub fn print_stderr_warning(msg: &str) {
+    eprintln!("{}", Yellow.paint(msg));
+}
+
+pub fn exit_error(msg: &str) -> ! {
     eprintln!("{}", Red.paint(msg));
     process::exit(1);
 }
+
+/// Ask the user a yes/no question on the terminal. Anything other than "y" or "yes" is a no.
+pub fn confirm(msg: &str) -> Result<bool> {
+    let answer = prompt(format!("{msg} [y/N]").as_str())?.to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+/// Ask the user a question on the terminal and return the trimmed answer
+pub fn prompt(msg: &str) -> Result<String> {
+    print!("{} ", Yellow.paint(msg));
+    io::stdout()
+        .flush()
+        .context("Could not write to terminal")?;
+
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .context("Could not read answer")?;
+
+    Ok(answer.trim().to_string())
+}
@@ -1,64 +1,128 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
+use std::collections::HashMap;
 use validator::Validate;
 
-use crate::cli_output::utils::{exit_error, print_success};
+use crate::canonical_url::host_range;
+use crate::canonical_url::{canonicalize, duplicate_key};
+use crate::cli_output::utils::{print_success, print_warning};
 use crate::config::Add;
-use crate::csv::{CsvLineReader, CsvLineWriter, create_csv};
-use crate::git::Git;
+use crate::csv::{
+    CsvChange, CsvLineReader, CsvLineWriter, Line, ParseMode, added_now, create_csv, csv_exists,
+    read_lines,
+};
+use crate::page_title::fetch_title;
+use crate::settings::{Settings, TagNormalizer, UrlPolicy};
+use crate::suggest::suggest_tags;
 
 pub fn add(add_opts: &Add, csv: &str) -> Result<()> {
     // Make sure Url is valid
     add_opts.validate()?;
 
-    // Open git repo unless user doesn't want to commit changes
-    let git = match add_opts.commit {
-        false => None,
-        true => Git::new(csv),
+    // Fail before fetching the title if it is a duplicate. It is checked again once the CSV is locked for writing.
+    if csv_exists(csv) {
+        let lines = read_lines(csv, ParseMode::Strict)?;
+        check_duplicate(add_opts.url.as_str(), &lines, &Settings::load(csv)?.urls)?;
+    }
+
+    // Fetched without the lock since it can take a while
+    let description = match (&add_opts.description, add_opts.fetch_title) {
+        (Some(description), false) => description.clone(),
+        (description, _) => title_or(add_opts.url.as_str(), description.as_deref()),
     };
 
-    // Make sure there aren't any uncommitted changes to the git repo before making any additional changes
-    if let Some(git) = &git {
-        if !git.is_clean()? {
-            exit_error("Git repo has uncommitted changes");
-        }
-    }
+    let change = CsvChange::begin(csv, add_opts.git.commit)?;
+
+    create_csv(csv)?;
+    let settings = Settings::load(csv)?;
+
+    // Read once for the duplicate check, the tag policy and the suggestions
+    let lines = CsvLineReader::new(csv)?.collect::<Result<Vec<Line>>>()?;
+
+    // Prevent duplicate bookmarks
+    check_duplicate(add_opts.url.as_str(), &lines, &settings.urls)?;
+
+    let url = match add_opts.canonical {
+        true => canonicalize(add_opts.url.as_str(), &settings.urls),
+        false => add_opts.url.clone(),
+    };
 
-    let created = create_csv(csv)?;
+    // Normalized first so a tag isn't suggested because it was typed differently, e.g. with an alias
+    let mut normalizer = TagNormalizer::new(&settings.tags).with_existing(&lines);
+    let mut tags = normalizer.normalize(&add_opts.tags);
 
-    // Prevent duplicate bookmarks (only on pre-existing files)
-    if !created && url_exists(add_opts.url.as_str(), csv)? {
-        exit_error(format!("{} has already been bookmarked", add_opts.url).as_str());
+    let suggested = suggest_tags(&lines, add_opts.url.as_str(), description.as_str(), &tags);
+    if add_opts.auto_tag {
+        tags.extend(suggested.iter().cloned());
+        tags = normalizer.normalize(&tags);
     }
 
     // Append bookmark to file
     let mut writer = CsvLineWriter::new(csv)?;
-    writer.write_line(
-        add_opts.url.as_str(),
-        add_opts.description.as_str(),
-        &add_opts.tags,
-    )?;
+    writer.write_line(&Line {
+        url: url.clone(),
+        description: description.clone(),
+        tags,
+        added: Some(added_now()),
+        ..Default::default()
+    })?;
 
-    if let Some(git) = &git {
-        git.add_and_commit_bookmark(add_opts.url.as_str(), add_opts.description.as_str())?;
-    }
+    change.finish(
+        format!("Add bookmark for {url} - {description}").as_str(),
+        "Bookmark added",
+    )?;
 
-    // Success
-    match &git {
-        Some(_) => print_success("Bookmark added and committed to git"),
-        None => print_success("Bookmark added"),
+    if !suggested.is_empty() {
+        match add_opts.auto_tag {
+            true => print_success(
+                format!("Tagged with suggested tags: {}", suggested.join(", ")).as_str(),
+            ),
+            false => print_warning(
+                format!(
+                    "Suggested tags: {} (--auto-tag to add them)",
+                    suggested.join(", ")
+                )
+                .as_str(),
+            ),
+        }
     }
     Ok(())
 }
 
-/// Check if URL already exists
-fn url_exists(url: &str, csv: &str) -> Result<bool> {
-    let reader = CsvLineReader::new(csv)?;
-
-    for line in reader {
-        if line?.url == url {
-            return Ok(true);
+/// The title of the page, or the fallback (or the host without one) if it can't be fetched, e.g. when offline
+fn title_or(url: &str, fallback: Option<&str>) -> String {
+    match fetch_title(url) {
+        Ok(title) => title,
+        Err(err) => {
+            let fallback = fallback
+                .map(String::from)
+                .or_else(|| host_range(url).map(|host| url[host].to_string()))
+                .unwrap_or_else(|| url.to_string());
+            print_warning(format!("{err:#}, using {fallback} as the description").as_str());
+            fallback
         }
     }
+}
+
+/// Fail if the URL, or a duplicate of it, has already been bookmarked
+pub fn check_duplicate<'a>(
+    url: &str,
+    lines: impl IntoIterator<Item = &'a Line>,
+    policy: &UrlPolicy,
+) -> Result<()> {
+    match bookmarked_urls(lines, policy).remove(&duplicate_key(url, policy)) {
+        Some(existing) if existing == url => bail!("{url} has already been bookmarked"),
+        Some(existing) => bail!("{url} has already been bookmarked as {existing}"),
+        None => Ok(()),
+    }
+}
 
-    Ok(false)
+/// All the URLs that have already been bookmarked, keyed by their duplicate key
+pub fn bookmarked_urls<'a>(
+    lines: impl IntoIterator<Item = &'a Line>,
+    policy: &UrlPolicy,
+) -> HashMap<String, String> {
+    lines
+        .into_iter()
+        .map(|line| (duplicate_key(line.url.as_str(), policy), line.url.clone()))
+        .collect()
 }
@@ -0,0 +1,383 @@
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::fs;
+use validator::ValidateUrl;
+
+use crate::canonical_url::duplicate_key;
+use crate::cli_output::utils::{print_success, print_warning};
+use crate::config::Check;
+use crate::csv::{
+    CsvChange, CsvLock, Line, LockMode, Schema, format_line, parse_line, rewrite_csv_rows,
+};
+use crate::settings::{Settings, UrlPolicy};
+
+pub fn check(check_opts: &Check, csv: &str) -> Result<()> {
+    // Only locked while reading unless the problems are being fixed
+    let (change, lock) = match check_opts.fix {
+        true => (Some(CsvChange::begin(csv, check_opts.git.commit)?), None),
+        false => (None, Some(CsvLock::acquire(csv, LockMode::Shared)?)),
+    };
+
+    let contents = fs::read_to_string(csv).context("Could not read CSV file")?;
+    let settings = Settings::load(csv)?;
+    let report = check_rows(contents.as_str(), &settings.urls);
+    drop(lock);
+
+    if report.problems.is_empty() {
+        print_success("No problems found");
+        return Ok(());
+    }
+
+    for problem in &report.problems {
+        let fixable = match (problem.fixable, check_opts.fix) {
+            (true, true) => " (fixed)",
+            (true, false) => " (fixable with --fix)",
+            (false, _) => "",
+        };
+        print_warning(format!("Line {}: {}{fixable}", problem.line, problem.message).as_str());
+    }
+
+    let fixable = report
+        .problems
+        .iter()
+        .filter(|problem| problem.fixable)
+        .count();
+    let unfixable = report.problems.len() - fixable;
+
+    if let Some(change) = change.filter(|_| fixable > 0) {
+        rewrite_csv_rows(csv, &report.fixed_rows)?;
+
+        let msg = format!("{fixable} problem(s) fixed");
+        change.finish(
+            format!("Fix bookmarks file\n\n{msg}").as_str(),
+            msg.as_str(),
+        )?;
+    }
+
+    // A non-zero exit code so scripts (e.g. CI) can fail on problems
+    let remaining = match check_opts.fix {
+        true => unfixable,
+        false => report.problems.len(),
+    };
+    if remaining > 0 {
+        bail!("{remaining} problem(s) found");
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Problem {
+    /// Line number in the file, starting at 1 for the header
+    line: usize,
+    message: String,
+    /// Whether --fix can fix it without needing to know what was meant
+    fixable: bool,
+}
+
+struct Report {
+    problems: Vec<Problem>,
+    /// Every row of the file (including the header) with the fixable problems fixed. Each row ends with the line
+    /// terminator it had, so fixing a file with CRLF line endings doesn't change them.
+    fixed_rows: Vec<String>,
+}
+
+impl Report {
+    fn problem(&mut self, line: usize, message: impl Into<String>, fixable: bool) {
+        self.problems.push(Problem {
+            line,
+            message: message.into(),
+            fixable,
+        });
+    }
+}
+
+fn check_rows(contents: &str, policy: &UrlPolicy) -> Report {
+    let mut report = Report {
+        problems: Vec::new(),
+        fixed_rows: Vec::new(),
+    };
+
+    // Line numbers start at 1 for the header
+    let mut rows = contents
+        .split_inclusive('\n')
+        .map(split_terminator)
+        .enumerate()
+        .map(|(index, (row, end))| (index + 1, row, end))
+        .peekable();
+
+    // A header that is added uses the terminator of the row after it
+    let header_end = match rows.peek() {
+        Some((_, _, end)) if !end.is_empty() => *end,
+        _ => "\n",
+    };
+
+    let schema = match rows.peek() {
+        None => {
+            report.problem(1, "Missing header", true);
+            Schema::required()
+        }
+        Some((_, header, _)) => match Schema::parse_header(header) {
+            Ok(schema) => {
+                if schema.header() != *header {
+                    report.problem(1, format!("Header should be {}", schema.header()), true);
+                }
+                rows.next();
+                schema
+            }
+            // A file without a header starts with a bookmark, which is left to be checked like the others.
+            // It was written before headers could mark values as escaped, so they are literal.
+            Err(_)
+                if parse_line(&Schema::required().with_escaping(false), header)
+                    .is_ok_and(|line| line.url.trim().validate_url()) =>
+            {
+                report.problem(1, "Missing header", true);
+                Schema::required().with_escaping(false)
+            }
+            Err(err) => {
+                // Can't tell what the columns are, so the bookmarks can't be checked
+                report.problem(1, err.to_string(), false);
+                return report;
+            }
+        },
+    };
+    report
+        .fixed_rows
+        .push(format!("{}{header_end}", schema.header()));
+
+    // Duplicate key -> line number and fixed row of the first bookmark with the URL
+    let mut urls: HashMap<String, (usize, String)> = HashMap::new();
+
+    for (number, row, end) in rows {
+        let line = match parse_line(&schema, row) {
+            Ok(line) => line,
+            Err(err) => {
+                report.problem(number, err.to_string(), false);
+                report.fixed_rows.push(format!("{row}{end}"));
+                continue;
+            }
+        };
+
+        let fixed = fix_line(&mut report, number, &line);
+
+        if !fixed.url.validate_url() {
+            report.problem(number, format!("Invalid URL {}", fixed.url), false);
+        }
+        if fixed.description.is_empty() {
+            report.problem(number, "Empty description", false);
+        }
+
+        let fixed_row = match fixed == line {
+            // Keep unchanged lines exactly as they are
+            true => row.to_string(),
+            false => format_line(&schema, &fixed),
+        };
+
+        let key = duplicate_key(fixed.url.as_str(), policy);
+        match urls.get(&key) {
+            // An exact copy can be removed without losing anything
+            Some((first, first_row)) if *first_row == fixed_row => {
+                report.problem(number, format!("Duplicate of line {first}"), true);
+                continue;
+            }
+            Some((first, _)) => report.problem(
+                number,
+                format!("Same URL as line {first}. Use bm dedupe to merge them"),
+                false,
+            ),
+            None => {
+                urls.insert(key, (number, fixed_row.clone()));
+            }
+        }
+
+        report.fixed_rows.push(format!("{fixed_row}{end}"));
+    }
+
+    report
+}
+
+/// The row without its line terminator, and the terminator ("\r\n", "\n" or nothing at the end of the file)
+fn split_terminator(row: &str) -> (&str, &str) {
+    let content = match row.strip_suffix('\n') {
+        Some(content) => content.strip_suffix('\r').unwrap_or(content),
+        None => row,
+    };
+    (content, &row[content.len()..])
+}
+
+/// The line with white space trimmed from every column and empty tags removed, reporting each of those problems.
+/// ARCHIVED doesn't need trimming since a value with white space isn't valid.
+fn fix_line(report: &mut Report, number: usize, line: &Line) -> Line {
+    let mut fixed = line.clone();
+    fixed.url = line.url.trim().to_string();
+    fixed.description = line.description.trim().to_string();
+    fixed.tags = line.tags.iter().map(|tag| tag.trim().to_string()).collect();
+    fixed.added = trim_optional(&line.added);
+    fixed.notes = trim_optional(&line.notes);
+
+    let has_white_space = fixed.url != line.url
+        || fixed.description != line.description
+        || fixed
+            .tags
+            .iter()
+            .zip(&line.tags)
+            .any(|(trimmed, tag)| trimmed != tag)
+        || fixed.added != line.added
+        || fixed.notes != line.notes;
+    if has_white_space {
+        report.problem(number, "Leading or trailing white space", true);
+    }
+
+    if fixed.tags.iter().any(|tag| tag.is_empty()) {
+        fixed.tags.retain(|tag| !tag.is_empty());
+        report.problem(number, "Empty tag (e.g. from a trailing comma)", true);
+    }
+
+    fixed
+}
+
+/// A value that is only white space is the same as no value
+fn trim_optional(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::check::{Problem, check_rows};
+    use crate::csv::Schema;
+    use crate::settings::UrlPolicy;
+
+    fn problems(contents: &str) -> Vec<(usize, String, bool)> {
+        check_rows(contents, &UrlPolicy::default())
+            .problems
+            .into_iter()
+            .map(
+                |Problem {
+                     line,
+                     message,
+                     fixable,
+                 }| (line, message, fixable),
+            )
+            .collect()
+    }
+
+    fn fixed(contents: &str) -> String {
+        check_rows(contents, &UrlPolicy::default())
+            .fixed_rows
+            .concat()
+    }
+
+    #[test]
+    fn valid_file() {
+        assert!(problems("URL|DESCRIPTION|TAGS\nhttps://a.com|A|x,y\n").is_empty());
+    }
+
+    #[test]
+    fn header() {
+        assert_eq!(
+            problems(""),
+            vec![(1, String::from("Missing header"), true)]
+        );
+        assert_eq!(fixed(""), "URL|DESCRIPTION|TAGS|#escaped\n");
+
+        let contents = "https://a.com|A|\n";
+        assert_eq!(
+            problems(contents),
+            vec![(1, String::from("Missing header"), true)]
+        );
+        assert_eq!(fixed(contents), "URL|DESCRIPTION|TAGS\nhttps://a.com|A|\n");
+
+        let contents = "url|description|tags\nhttps://a.com|A|\n";
+        assert_eq!(
+            problems(contents),
+            vec![(
+                1,
+                String::from("Header should be URL|DESCRIPTION|TAGS"),
+                true
+            )]
+        );
+
+        let problems = problems("URL|DESCRIPTION|COLOR\nhttps://a.com|A|\n");
+        assert_eq!(problems.len(), 1);
+        assert!(!problems[0].2);
+    }
+
+    #[test]
+    fn unfixable_lines_are_kept() {
+        let contents = "URL|DESCRIPTION|TAGS\nhttps://a.com|A|b|c\nnot a url||\nhttps://b.com|B|\n";
+        let problems = problems(contents);
+
+        assert_eq!(problems.len(), 3, "{problems:?}");
+        assert_eq!(problems[0].0, 2);
+        assert!(problems[0].1.contains("4 columns instead of 3"));
+        assert_eq!(
+            problems[1],
+            (3, String::from("Invalid URL not a url"), false)
+        );
+        assert_eq!(problems[2], (3, String::from("Empty description"), false));
+        assert_eq!(fixed(contents), contents);
+    }
+
+    #[test]
+    fn fixes() {
+        let contents = "URL|DESCRIPTION|TAGS\n https://a.com|A |x,,y,\nhttps://b.com|B|\nhttps://b.com|B|\nhttp://A.com|Other|\n";
+
+        assert_eq!(
+            problems(contents),
+            vec![
+                (2, String::from("Leading or trailing white space"), true),
+                (
+                    2,
+                    String::from("Empty tag (e.g. from a trailing comma)"),
+                    true
+                ),
+                (4, String::from("Duplicate of line 3"), true),
+                (
+                    5,
+                    String::from("Same URL as line 2. Use bm dedupe to merge them"),
+                    false
+                ),
+            ]
+        );
+        assert_eq!(
+            fixed(contents),
+            "URL|DESCRIPTION|TAGS\nhttps://a.com|A|x,y\nhttps://b.com|B|\nhttp://A.com|Other|\n"
+        );
+    }
+
+    #[test]
+    fn every_column_is_trimmed() {
+        let header = Schema::latest().header();
+        let contents = format!(
+            "{header}\nhttps://a.com|A||2021-07-22T10:46:41Z | a note |true\nhttps://b.com|B||| |false\n"
+        );
+
+        assert_eq!(
+            problems(contents.as_str()),
+            vec![
+                (2, String::from("Leading or trailing white space"), true),
+                (3, String::from("Leading or trailing white space"), true),
+            ]
+        );
+        assert_eq!(
+            fixed(contents.as_str()),
+            format!(
+                "{header}\nhttps://a.com|A||2021-07-22T10:46:41Z|a note|true\nhttps://b.com|B||||false\n"
+            )
+        );
+    }
+
+    #[test]
+    fn line_terminators_are_kept() {
+        let contents = "https://a.com|A |\r\nhttps://b.com|B|\nhttps://c.com|C|";
+
+        assert_eq!(
+            fixed(contents),
+            "URL|DESCRIPTION|TAGS\r\nhttps://a.com|A|\r\nhttps://b.com|B|\nhttps://c.com|C|"
+        );
+    }
+}
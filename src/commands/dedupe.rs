@@ -0,0 +1,288 @@
+use anyhow::{Result, bail};
+use std::collections::{HashMap, HashSet};
+
+use crate::canonical_url::duplicate_key;
+use crate::cli_output::search_result_output::{MatchedBookmark, SearchResultOutput};
+use crate::cli_output::utils::{print_success, print_warning, prompt};
+use crate::config::{Dedupe, KeepDescription};
+use crate::csv::{CsvChange, CsvLineReader, Line, ParseMode, read_lines, rewrite_csv};
+use crate::settings::{Settings, TagNormalizer, UrlPolicy};
+
+pub fn dedupe(dedupe_opts: &Dedupe, csv: &str) -> Result<()> {
+    let settings = Settings::load(csv)?;
+
+    // The CSV isn't locked while asking what to merge, so the decisions are applied to the file as it is afterwards
+    let decisions = decide(dedupe_opts, &read_lines(csv, ParseMode::Strict)?, &settings)?;
+    if decisions.is_empty() {
+        return Ok(());
+    }
+
+    let change = CsvChange::begin(csv, dedupe_opts.git.commit)?;
+
+    let reader = CsvLineReader::new(csv)?;
+    let schema = reader.schema().clone();
+    let mut lines = reader.collect::<Result<Vec<Line>>>()?;
+
+    let mut normalizer = TagNormalizer::new(&settings.tags).with_existing(&lines);
+    let mut removed = HashSet::new();
+    let mut merged_urls = Vec::new();
+
+    for group in duplicate_groups(&lines, &settings.urls) {
+        let urls = group_urls(&lines, &group);
+        // Groups that changed since they were shown are left alone
+        let Some(description) = decisions.get(&urls) else {
+            continue;
+        };
+
+        let duplicates = group
+            .iter()
+            .map(|index| &lines[*index])
+            .collect::<Vec<&Line>>();
+        let merged = merge(&duplicates, *description, &mut normalizer);
+        merged_urls.push(merged.url.clone());
+        lines[group[0]] = merged;
+        removed.extend(group[1..].iter().copied());
+    }
+
+    if removed.is_empty() {
+        print_warning("No bookmarks merged since the duplicates changed");
+        return Ok(());
+    }
+
+    // The merged bookmark is where the first duplicate was
+    let lines = lines
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !removed.contains(index))
+        .map(|(_, line)| line)
+        .collect::<Vec<Line>>();
+    rewrite_csv(csv, &schema, &lines)?;
+
+    change.finish(
+        format!(
+            "Merge duplicate bookmarks\n\n{} bookmark(s) removed\n\n{}",
+            removed.len(),
+            merged_urls.join("\n")
+        )
+        .as_str(),
+        format!("{} duplicate bookmark(s) merged", removed.len()).as_str(),
+    )
+}
+
+/// Which groups of duplicates to merge, keyed by the URLs in the group, and the index in the group of the
+/// description to keep
+fn decide(
+    dedupe_opts: &Dedupe,
+    lines: &[Line],
+    settings: &Settings,
+) -> Result<HashMap<Vec<String>, usize>> {
+    let groups = duplicate_groups(lines, &settings.urls);
+    if groups.is_empty() {
+        print_success("No duplicate bookmarks");
+        return Ok(HashMap::new());
+    }
+
+    let mut decisions = HashMap::new();
+    for group in &groups {
+        let duplicates = group
+            .iter()
+            .map(|index| &lines[*index])
+            .collect::<Vec<&Line>>();
+
+        let mut out = SearchResultOutput::new();
+        for line in &duplicates {
+            out.add_matched_bookmark(MatchedBookmark::new_tags_only(
+                line.url.as_str(),
+                line.description.as_str(),
+                line.tags.clone(),
+            ));
+        }
+        println!();
+        out.print_numbered();
+
+        let description = match dedupe_opts.auto {
+            true => None,
+            false => {
+                let answer = prompt(
+                    format!(
+                        "Merge these bookmarks? [y/N, 1-{} to keep that description, q to quit]",
+                        duplicates.len()
+                    )
+                    .as_str(),
+                )?;
+                match parse_answer(&answer, duplicates.len())? {
+                    Answer::Merge(description) => description,
+                    Answer::Skip => continue,
+                    Answer::Quit => break,
+                }
+            }
+        };
+        let description = description.unwrap_or_else(|| match dedupe_opts.description {
+            KeepDescription::Longest => longest_description(&duplicates),
+            KeepDescription::First => 0,
+        });
+
+        decisions.insert(group_urls(lines, group), description);
+    }
+
+    if decisions.is_empty() {
+        print_warning("No bookmarks merged");
+    }
+    Ok(decisions)
+}
+
+fn group_urls(lines: &[Line], group: &[usize]) -> Vec<String> {
+    group
+        .iter()
+        .map(|index| lines[*index].url.clone())
+        .collect()
+}
+
+/// Indexes of the lines with the same canonical URL, for every URL with more than one line (in file order)
+fn duplicate_groups(lines: &[Line], policy: &UrlPolicy) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    // Duplicate key -> index in groups
+    let mut group_indexes: HashMap<String, usize> = HashMap::new();
+
+    for (index, line) in lines.iter().enumerate() {
+        let key = duplicate_key(line.url.as_str(), policy);
+        match group_indexes.get(&key) {
+            Some(group) => groups[*group].push(index),
+            None => {
+                group_indexes.insert(key, groups.len());
+                groups.push(vec![index]);
+            }
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Answer {
+    /// Merge, keeping the description of the bookmark at the index if one was picked
+    Merge(Option<usize>),
+    Skip,
+    /// Stop asking. The groups already merged are still saved
+    Quit,
+}
+
+fn parse_answer(answer: &str, count: usize) -> Result<Answer> {
+    match answer.to_lowercase().as_str() {
+        "" | "n" | "no" => Ok(Answer::Skip),
+        "y" | "yes" => Ok(Answer::Merge(None)),
+        "q" | "quit" => Ok(Answer::Quit),
+        number => match number.parse::<usize>() {
+            Ok(number) if (1..=count).contains(&number) => Ok(Answer::Merge(Some(number - 1))),
+            _ => bail!("Expected y, n, q or a number from 1 to {count}, not {answer}"),
+        },
+    }
+}
+
+/// Index of the longest description. The first one wins a tie.
+fn longest_description(duplicates: &[&Line]) -> usize {
+    let mut longest = 0;
+    for (index, line) in duplicates.iter().enumerate() {
+        if line.description.chars().count() > duplicates[longest].description.chars().count() {
+            longest = index;
+        }
+    }
+    longest
+}
+
+/// Combine the duplicates into the first one. It keeps every tag and note, the earliest added date and is only
+/// archived if every duplicate was.
+fn merge(duplicates: &[&Line], description: usize, normalizer: &mut TagNormalizer) -> Line {
+    let first = duplicates[0];
+
+    let tags = duplicates
+        .iter()
+        .flat_map(|line| line.tags.iter().cloned())
+        .collect::<Vec<String>>();
+
+    let mut notes: Vec<&str> = Vec::new();
+    for line in duplicates {
+        if let Some(note) = line.notes.as_deref().filter(|note| !note.is_empty()) {
+            if !notes.contains(&note) {
+                notes.push(note);
+            }
+        }
+    }
+
+    Line {
+        url: first.url.clone(),
+        description: duplicates[description].description.clone(),
+        tags: normalizer.normalize(&tags),
+        added: duplicates
+            .iter()
+            .filter_map(|line| line.added.clone())
+            .min(),
+        notes: match notes.is_empty() {
+            true => first.notes.clone(),
+            false => Some(notes.join("; ")),
+        },
+        archived: duplicates.iter().all(|line| line.archived),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::dedupe::{
+        Answer, duplicate_groups, longest_description, merge, parse_answer,
+    };
+    use crate::csv::Line;
+    use crate::settings::{Settings, TagNormalizer, UrlPolicy};
+
+    #[test]
+    fn groups() {
+        let lines = [
+            Line::new("https://example.com/", "A", &[]),
+            Line::new("https://rust-lang.org", "B", &[]),
+            Line::new("http://EXAMPLE.com?utm_source=x", "C", &[]),
+            Line::new("https://go.dev", "D", &[]),
+            Line::new("https://example.com", "E", &[]),
+        ];
+
+        assert_eq!(
+            duplicate_groups(&lines, &UrlPolicy::default()),
+            vec![vec![0, 2, 4]]
+        );
+    }
+
+    #[test]
+    fn answers() {
+        assert_eq!(parse_answer("", 2).unwrap(), Answer::Skip);
+        assert_eq!(parse_answer("Y", 2).unwrap(), Answer::Merge(None));
+        assert_eq!(parse_answer("2", 2).unwrap(), Answer::Merge(Some(1)));
+        assert_eq!(parse_answer("q", 2).unwrap(), Answer::Quit);
+        assert!(parse_answer("3", 2).is_err());
+    }
+
+    #[test]
+    fn merge_lines() {
+        let mut first = Line::new("https://example.com", "Example", &["web", "Docs"]);
+        first.added = Some(String::from("2024-05-01T00:00:00Z"));
+        first.notes = Some(String::new());
+        let mut second = Line::new("http://example.com/", "Example site", &["docs", "ref"]);
+        second.added = Some(String::from("2023-01-01T00:00:00Z"));
+        second.notes = Some(String::from("Read later"));
+        second.archived = true;
+        let duplicates = [&first, &second];
+
+        let settings = Settings::default();
+        let mut normalizer = TagNormalizer::new(&settings.tags);
+        let merged = merge(
+            &duplicates,
+            longest_description(&duplicates),
+            &mut normalizer,
+        );
+
+        assert_eq!(merged.url, "https://example.com");
+        assert_eq!(merged.description, "Example site");
+        assert_eq!(merged.tags, vec!["web", "Docs", "ref"]);
+        assert_eq!(merged.added.as_deref(), Some("2023-01-01T00:00:00Z"));
+        assert_eq!(merged.notes.as_deref(), Some("Read later"));
+        assert!(!merged.archived);
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{Result, bail, ensure};
+use validator::Validate;
+
+use crate::cli_output::utils::print_warning;
+use crate::commands::add::check_duplicate;
+use crate::config::Edit;
+use crate::csv::{CsvChange, CsvLineReader, Line, rewrite_csv};
+use crate::settings::{Settings, TagNormalizer};
+
+pub fn edit(edit_opts: &Edit, csv: &str) -> Result<()> {
+    // Make sure the new URL is valid
+    edit_opts.validate()?;
+
+    ensure!(
+        edit_opts.new_url.is_some()
+            || edit_opts.description.is_some()
+            || !edit_opts.tags.is_empty()
+            || !edit_opts.remove_tags.is_empty(),
+        "Nothing to edit. Use --url, --description, --tag or --remove-tag"
+    );
+
+    let change = CsvChange::begin(csv, edit_opts.git.commit)?;
+
+    let reader = CsvLineReader::new(csv)?;
+    let schema = reader.schema().clone();
+    let mut lines = reader.collect::<Result<Vec<Line>>>()?;
+
+    let index = match lines.iter().position(|line| line.url == edit_opts.url) {
+        Some(index) => index,
+        None => bail!("{} has not been bookmarked", edit_opts.url),
+    };
+
+    let settings = Settings::load(csv)?;
+
+    // Prevent duplicate bookmarks, the same way as add. The bookmark can still change to a URL that only it matches
+    if let Some(new_url) = &edit_opts.new_url {
+        let others = lines
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .map(|(_, line)| line);
+        check_duplicate(new_url, others, &settings.urls)?;
+    }
+    let mut normalizer = TagNormalizer::new(&settings.tags).with_existing(&lines);
+
+    let changes = apply_edit(edit_opts, &mut lines[index], &mut normalizer);
+    if changes.is_empty() {
+        print_warning("Bookmark is unchanged");
+        return Ok(());
+    }
+
+    rewrite_csv(csv, &schema, &lines)?;
+
+    let msg = format!(
+        "Edit bookmark for {}\n\n{}",
+        lines[index].url,
+        changes.join("\n")
+    );
+    change.finish(msg.as_str(), "Bookmark edited")
+}
+
+/// Apply the requested changes to the line.
+/// Returns a human-readable description of each change that was actually made.
+fn apply_edit(edit_opts: &Edit, line: &mut Line, normalizer: &mut TagNormalizer) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    if let Some(new_url) = &edit_opts.new_url {
+        if *new_url != line.url {
+            changes.push(format!("URL: {} -> {}", line.url, new_url));
+            line.url = new_url.clone();
+        }
+    }
+
+    if let Some(description) = &edit_opts.description {
+        if *description != line.description {
+            changes.push(format!(
+                "Description: {} -> {}",
+                line.description, description
+            ));
+            line.description = description.clone();
+        }
+    }
+
+    // Tags are case insensitive, so only remove/add them if a tag with the same case insensitive name is (not) present
+    let mut removed = Vec::new();
+    for remove_tag in &edit_opts.remove_tags {
+        let remove_tag = remove_tag.to_lowercase();
+        line.tags.retain(|tag| {
+            let keep = tag.to_lowercase() != remove_tag;
+            if !keep {
+                removed.push(tag.clone());
+            }
+            keep
+        });
+    }
+
+    let mut added = Vec::new();
+    for tag in normalizer.normalize(&edit_opts.tags) {
+        let lowercase_tag = tag.to_lowercase();
+        if !line.tags.iter().any(|t| t.to_lowercase() == lowercase_tag) {
+            line.tags.push(tag.clone());
+            added.push(tag);
+        }
+    }
+
+    if !removed.is_empty() {
+        changes.push(format!("Removed tags: {}", removed.join(", ")));
+    }
+    if !added.is_empty() {
+        changes.push(format!("Added tags: {}", added.join(", ")));
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::edit::apply_edit;
+    use crate::config::Edit;
+    use crate::csv::Line;
+    use crate::settings::{TagNormalizer, TagPolicy};
+
+    fn line() -> Line {
+        Line::new("https://google.com", "Google", &["Search", "Engine"])
+    }
+
+    #[test]
+    fn change_url_and_description() {
+        let mut line = line();
+        let changes = apply_edit(
+            &Edit {
+                new_url: Some(String::from("https://www.google.com")),
+                description: Some(String::from("Google search engine")),
+                ..Edit::new("https://google.com")
+            },
+            &mut line,
+            &mut TagNormalizer::new(&TagPolicy::default()),
+        );
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(line.url, "https://www.google.com");
+        assert_eq!(line.description, "Google search engine");
+    }
+
+    #[test]
+    fn add_and_remove_tags() {
+        let mut line = line();
+        let changes = apply_edit(
+            &Edit {
+                tags: vec![String::from("search"), String::from("Google")],
+                remove_tags: vec![String::from("engine")],
+                ..Edit::new("https://google.com")
+            },
+            &mut line,
+            &mut TagNormalizer::new(&TagPolicy::default()),
+        );
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(line.tags, vec!["Search", "Google"]);
+    }
+
+    #[test]
+    fn unchanged() {
+        let mut line = line();
+        let changes = apply_edit(
+            &Edit {
+                description: Some(String::from("Google")),
+                tags: vec![String::from("Search")],
+                ..Edit::new("https://google.com")
+            },
+            &mut line,
+            &mut TagNormalizer::new(&TagPolicy::default()),
+        );
+
+        assert!(changes.is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use std::collections::HashMap;
+use std::fs;
+
+use crate::cli_output::utils::print_success;
+use crate::commands::search::{build_query, build_tag_expr, match_line};
+use crate::config::{Export, ExportFormat, ExportHtml, FolderMode};
+use crate::csv::{Line, ParseMode, read_lines};
+use crate::netscape::{self, NetscapeBookmark, NetscapeFolder};
+
+pub fn export(export_opts: &Export, csv: &str, parse_mode: ParseMode) -> Result<()> {
+    match &export_opts.format {
+        ExportFormat::Html(html_opts) => export_html(html_opts, csv, parse_mode),
+    }
+}
+
+fn export_html(html_opts: &ExportHtml, csv: &str, parse_mode: ParseMode) -> Result<()> {
+    let query = build_query(&html_opts.regex)?;
+    let tag_expr = build_tag_expr(&html_opts.filters.where_expr)?;
+
+    let mut lines = Vec::new();
+    for line in read_lines(csv, parse_mode)? {
+        if match_line(&query, &html_opts.filters.tags, &tag_expr, line.clone()).is_some() {
+            lines.push(line);
+        }
+    }
+
+    let count = lines.len();
+    let html = netscape::to_html(&to_folders(lines, html_opts.folders));
+
+    match &html_opts.output {
+        Some(output) => {
+            fs::write(output, html).with_context(|| format!("Could not write {output}"))?;
+            print_success(format!("Exported {count} bookmarks to {output}").as_str());
+        }
+        None => print!("{html}"),
+    }
+
+    Ok(())
+}
+
+/// Organize the bookmarks into folders based on their tags.
+/// Untagged bookmarks are left at the top level.
+fn to_folders(lines: Vec<Line>, folder_mode: FolderMode) -> NetscapeFolder {
+    let mut root = NetscapeFolder {
+        name: String::from("Bookmarks"),
+        folders: Vec::new(),
+        bookmarks: Vec::new(),
+    };
+    // Tags are case insensitive so they share a folder (named after the first casing seen)
+    let mut folder_index = HashMap::new();
+
+    for line in lines {
+        let folder_tags = match folder_mode {
+            FolderMode::FirstTag => line.tags.iter().take(1).cloned().collect(),
+            FolderMode::PerTag => line.tags.clone(),
+            FolderMode::Flat => Vec::new(),
+        };
+
+        if folder_tags.is_empty() {
+            root.bookmarks.push(to_bookmark(line, folder_mode));
+            continue;
+        }
+
+        for tag in folder_tags {
+            let index = *folder_index.entry(tag.to_lowercase()).or_insert_with(|| {
+                root.folders.push(NetscapeFolder {
+                    name: tag.clone(),
+                    folders: Vec::new(),
+                    bookmarks: Vec::new(),
+                });
+                root.folders.len() - 1
+            });
+            root.folders[index]
+                .bookmarks
+                .push(to_bookmark(line.clone(), folder_mode));
+        }
+    }
+
+    root.folders
+        .sort_by_key(|folder| folder.name.to_lowercase());
+
+    root
+}
+
+fn to_bookmark(line: Line, folder_mode: FolderMode) -> NetscapeBookmark {
+    let add_date = line
+        .added
+        .and_then(|added| DateTime::parse_from_rfc3339(added.as_str()).ok())
+        .map(|added| added.timestamp());
+
+    NetscapeBookmark {
+        url: line.url,
+        title: line.description,
+        // Tags are represented by the folders except when there aren't any folders
+        tags: match folder_mode {
+            FolderMode::Flat => line.tags,
+            _ => Vec::new(),
+        },
+        add_date,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::export::to_folders;
+    use crate::config::FolderMode;
+    use crate::csv::Line;
+
+    fn lines() -> Vec<Line> {
+        vec![
+            Line {
+                added: Some(String::from("2021-07-22T10:46:41Z")),
+                ..Line::new("https://google.com", "Google", &["Search", "Google"])
+            },
+            Line::new("https://bing.com", "Bing", &["search"]),
+            Line::new("https://github.com", "GitHub", &[]),
+        ]
+    }
+
+    #[test]
+    fn first_tag() {
+        let root = to_folders(lines(), FolderMode::FirstTag);
+
+        assert_eq!(root.folders.len(), 1);
+        assert_eq!(root.folders[0].name, "Search");
+        assert_eq!(root.folders[0].bookmarks.len(), 2);
+        assert_eq!(root.folders[0].bookmarks[0].add_date, Some(1626950801));
+        assert!(root.folders[0].bookmarks[0].tags.is_empty());
+        assert_eq!(root.bookmarks.len(), 1);
+    }
+
+    #[test]
+    fn per_tag() {
+        let root = to_folders(lines(), FolderMode::PerTag);
+
+        assert_eq!(root.folders.len(), 2);
+        assert_eq!(root.folders[0].name, "Google");
+        assert_eq!(root.folders[0].bookmarks.len(), 1);
+        assert_eq!(root.folders[1].name, "Search");
+        assert_eq!(root.folders[1].bookmarks.len(), 2);
+        assert_eq!(root.bookmarks.len(), 1);
+    }
+
+    #[test]
+    fn flat() {
+        let root = to_folders(lines(), FolderMode::Flat);
+
+        assert!(root.folders.is_empty());
+        assert_eq!(root.bookmarks.len(), 3);
+        assert_eq!(root.bookmarks[0].tags, vec!["Search", "Google"]);
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use std::fs;
+use std::path::Path;
+use validator::ValidateUrl;
+
+use crate::canonical_url::duplicate_key;
+use crate::cli_output::utils::{print_success, print_warning};
+use crate::commands::add::bookmarked_urls;
+use crate::config::{Import, ImportFormat, ImportHtml};
+use crate::csv::{CsvChange, CsvLineReader, CsvLineWriter, Line, create_csv, format_added};
+use crate::netscape;
+use crate::settings::{Settings, TagNormalizer};
+
+pub fn import(import_opts: &Import, csv: &str) -> Result<()> {
+    match &import_opts.format {
+        ImportFormat::Html(html_opts) => import_html(html_opts, csv),
+    }
+}
+
+fn import_html(html_opts: &ImportHtml, csv: &str) -> Result<()> {
+    let html = fs::read_to_string(&html_opts.file)
+        .with_context(|| format!("Could not read {}", html_opts.file))?;
+
+    let change = CsvChange::begin(csv, html_opts.git.commit)?;
+
+    create_csv(csv)?;
+
+    let settings = Settings::load(csv)?;
+
+    // Prevent duplicate bookmarks, including duplicates within the import file
+    let lines = CsvLineReader::new(csv)?.collect::<Result<Vec<Line>>>()?;
+    let mut urls = bookmarked_urls(&lines, &settings.urls);
+    let mut normalizer = TagNormalizer::new(&settings.tags).with_existing(&lines);
+
+    let mut writer = CsvLineWriter::new(csv)?;
+    let (mut added, mut skipped, mut invalid) = (0, 0, 0);
+
+    for bookmark in netscape::parse(&html) {
+        // Browsers also export things like bookmarklets (javascript:) and internal pages which aren't web bookmarks
+        let is_web_url =
+            bookmark.url.starts_with("http://") || bookmark.url.starts_with("https://");
+        if !is_web_url || !bookmark.url.validate_url() {
+            print_warning(format!("Skipping invalid URL {}", bookmark.url).as_str());
+            invalid += 1;
+            continue;
+        }
+
+        let key = duplicate_key(bookmark.url.as_str(), &settings.urls);
+        if urls.contains_key(&key) {
+            skipped += 1;
+            continue;
+        }
+        urls.insert(key, bookmark.url.clone());
+
+        // Description is required, so fall back to the URL if the bookmark has no title
+        let description = match bookmark.title.as_str() {
+            "" => bookmark.url.clone(),
+            title => String::from(title),
+        };
+
+        // Only written if the CSV has an ADDED column
+        let added_date = bookmark
+            .add_date
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .map(format_added);
+
+        writer.write_line(&Line {
+            url: bookmark.url,
+            description,
+            tags: normalizer.normalize(&bookmark.tags),
+            added: added_date,
+            ..Default::default()
+        })?;
+        added += 1;
+    }
+
+    let summary =
+        format!("Imported {added} bookmarks ({skipped} already bookmarked, {invalid} invalid)");
+    if added == 0 {
+        print_success(summary.as_str());
+        return Ok(());
+    }
+
+    // A single commit for the whole import
+    let file_name = Path::new(&html_opts.file)
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    change.finish(
+        format!("Import {added} bookmarks from {file_name}").as_str(),
+        summary.as_str(),
+    )
+}
@@ -0,0 +1,426 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use ureq::{Agent, AgentBuilder, ErrorKind, Request};
+use url::Url;
+
+use crate::canonical_url::duplicate_key;
+use crate::cli_output::utils::print_warning;
+use crate::commands::search::{build_query, build_tag_expr, match_line};
+use crate::config::Linkcheck;
+use crate::csv::{CsvChange, CsvLineReader, Line, ParseMode, read_lines, rewrite_csv};
+use crate::settings::{Settings, TagNormalizer};
+
+/// Tag added to bookmarks with a broken link by --tag-dead
+const DEAD_TAG: &str = "dead";
+
+/// Give up on a link that redirects more than this many times
+const MAX_REDIRECTS: usize = 10;
+
+/// How long to wait before trying a server that couldn't be reached again
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub fn linkcheck(linkcheck_opts: &Linkcheck, csv: &str, parse_mode: ParseMode) -> Result<()> {
+    let changes_file = linkcheck_opts.tag_dead || linkcheck_opts.update_redirects;
+
+    let query = build_query(&linkcheck_opts.regex)?;
+    let tag_expr = build_tag_expr(&linkcheck_opts.filters.where_expr)?;
+
+    // Lines can only be skipped if the file isn't going to be rewritten.
+    // The CSV isn't locked while the links are checked since it can take a while.
+    let lines = read_lines(
+        csv,
+        match changes_file {
+            true => ParseMode::Strict,
+            false => parse_mode,
+        },
+    )?;
+
+    // Everything is checked unless filtered
+    let urls = lines
+        .into_iter()
+        .filter(|line| {
+            match_line(
+                &query,
+                &linkcheck_opts.filters.tags,
+                &tag_expr,
+                line.clone(),
+            )
+            .is_some()
+        })
+        .map(|line| line.url)
+        .collect::<Vec<String>>();
+
+    let checks = check_all(
+        &urls,
+        linkcheck_opts.jobs,
+        Duration::from_secs(linkcheck_opts.timeout),
+    );
+
+    let (mut ok, mut redirected, mut failed, mut dead) = (0, 0, 0, 0);
+    for (url, check) in urls.iter().zip(&checks) {
+        let msg = check.describe(url);
+        if check.is_dead() {
+            dead += 1;
+            print_warning(msg.as_str());
+        } else if !check.is_working() {
+            failed += 1;
+            print_warning(msg.as_str());
+        } else if !check.redirects.is_empty() {
+            redirected += 1;
+            print_warning(msg.as_str());
+        } else {
+            ok += 1;
+            println!("{msg}");
+        }
+    }
+    println!("{ok} ok, {redirected} redirected, {failed} failed, {dead} dead");
+
+    if !changes_file {
+        return Ok(());
+    }
+
+    // When nothing could be reached it is more likely that this machine is offline than that every link is dead
+    let tag_dead = linkcheck_opts.tag_dead
+        && !checks
+            .iter()
+            .all(|check| matches!(&check.outcome, Err(failure) if failure.unreachable));
+    if linkcheck_opts.tag_dead && !tag_dead {
+        print_warning(
+            "None of the links could be reached, so none are tagged dead. Are you offline?",
+        );
+    }
+
+    let checks = urls
+        .into_iter()
+        .zip(checks)
+        .collect::<HashMap<String, LinkCheck>>();
+
+    let change = CsvChange::begin(csv, linkcheck_opts.git.commit)?;
+
+    // Read again since the file may have changed while the links were checked, so bookmarks are found by URL
+    let reader = CsvLineReader::new(csv)?;
+    let schema = reader.schema().clone();
+    let mut lines = reader.collect::<Result<Vec<Line>>>()?;
+
+    let settings = Settings::load(csv)?;
+    // Only the tag that is added follows the tag policy, the bookmark's other tags are left alone
+    let dead_tag = TagNormalizer::new(&settings.tags)
+        .with_existing(&lines)
+        .normalize(&[String::from(DEAD_TAG)])
+        .pop()
+        .unwrap_or_else(|| String::from(DEAD_TAG));
+    let mut bookmarked = lines
+        .iter()
+        .map(|line| duplicate_key(line.url.as_str(), &settings.urls))
+        .collect::<HashSet<String>>();
+
+    let (mut tagged, mut updated) = (0, 0);
+    for line in lines.iter_mut() {
+        let Some(check) = checks.get(&line.url) else {
+            continue;
+        };
+
+        let is_tagged = line
+            .tags
+            .iter()
+            .any(|tag| tag.to_lowercase() == dead_tag.to_lowercase());
+        if tag_dead && check.is_dead() && !is_tagged {
+            line.tags.push(dead_tag.clone());
+            tagged += 1;
+        }
+
+        let Some(moved_to) = check.moved_to().filter(|_| linkcheck_opts.update_redirects) else {
+            continue;
+        };
+        // Don't create a duplicate, unless it is a duplicate of itself (e.g. http redirecting to https)
+        let key = duplicate_key(moved_to, &settings.urls);
+        if key != duplicate_key(line.url.as_str(), &settings.urls) && bookmarked.contains(&key) {
+            print_warning(
+                format!(
+                    "Not updating {} since {moved_to} is already bookmarked",
+                    line.url
+                )
+                .as_str(),
+            );
+            continue;
+        }
+        bookmarked.insert(key);
+        line.url = String::from(moved_to);
+        updated += 1;
+    }
+
+    if tagged + updated == 0 {
+        print_warning("No bookmarks changed");
+        return Ok(());
+    }
+
+    rewrite_csv(csv, &schema, &lines)?;
+
+    let summary = format!("{tagged} bookmark(s) tagged {dead_tag} and {updated} URL(s) updated");
+    change.finish(
+        format!("Check links\n\n{summary}").as_str(),
+        summary.as_str(),
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct LinkCheck {
+    /// The status of each redirect and the URL it went to
+    redirects: Vec<(u16, String)>,
+    /// The final status or why there wasn't one
+    outcome: Result<u16, Failure>,
+}
+
+/// Why a link couldn't be checked
+#[derive(Debug, PartialEq, Eq)]
+struct Failure {
+    message: String,
+    /// The server couldn't be reached, even when it was tried again (e.g. the host doesn't exist or refused the
+    /// connection)
+    unreachable: bool,
+}
+
+impl LinkCheck {
+    /// Only a link that is known to be gone is dead. Other errors (e.g. 403 from bot protection, 429, 5xx or a
+    /// timeout) can be temporary, so they are failures.
+    fn is_dead(&self) -> bool {
+        match &self.outcome {
+            Ok(status) => *status == 404 || *status == 410,
+            Err(failure) => failure.unreachable,
+        }
+    }
+
+    /// The link ends up at a page that works
+    fn is_working(&self) -> bool {
+        matches!(self.outcome, Ok(status) if status < 400)
+    }
+
+    /// Where the link has permanently moved to. Only if every redirect was permanent and it ends up somewhere that works.
+    fn moved_to(&self) -> Option<&str> {
+        let is_permanent = self
+            .redirects
+            .iter()
+            .all(|(status, _)| *status == 301 || *status == 308);
+
+        match is_permanent && self.is_working() {
+            true => self.redirects.last().map(|(_, url)| url.as_str()),
+            false => None,
+        }
+    }
+
+    fn describe(&self, url: &str) -> String {
+        let outcome = match &self.outcome {
+            Ok(status) => status.to_string(),
+            Err(failure) => format!("ERROR ({})", failure.message),
+        };
+
+        match (self.redirects.first(), self.redirects.last()) {
+            (Some((status, _)), Some((_, last))) => {
+                let permanent = match self.moved_to() {
+                    Some(_) => " (moved permanently)",
+                    None => "",
+                };
+                format!("{status} {url} -> {last} {outcome}{permanent}")
+            }
+            _ => format!("{outcome} {url}"),
+        }
+    }
+}
+
+/// Check the URLs with up to `jobs` requests at the same time. The results are in the same order as the URLs.
+fn check_all(urls: &[String], jobs: usize, timeout: Duration) -> Vec<LinkCheck> {
+    // Redirects are followed by hand so they can be reported
+    let agent = AgentBuilder::new()
+        .timeout(timeout)
+        .redirects(0)
+        .user_agent(concat!("bm/", env!("CARGO_PKG_VERSION")))
+        .build();
+
+    let next = AtomicUsize::new(0);
+    let results = urls
+        .iter()
+        .map(|_| Mutex::new(None))
+        .collect::<Vec<Mutex<Option<LinkCheck>>>>();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, urls.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(url) = urls.get(index) else {
+                        break;
+                    };
+                    let check = check_url(&agent, url);
+                    *results[index].lock().unwrap() = Some(check);
+                }
+            });
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|result| result.into_inner().unwrap().unwrap())
+        .collect()
+}
+
+fn check_url(agent: &Agent, url: &str) -> LinkCheck {
+    let mut redirects = Vec::new();
+    let mut current = String::from(url);
+
+    loop {
+        let outcome = request(agent, current.as_str());
+        let (status, location) = match outcome {
+            Ok((status, Some(location))) if (300..400).contains(&status) => (status, location),
+            outcome => {
+                return LinkCheck {
+                    redirects,
+                    outcome: outcome.map(|(status, _)| status),
+                };
+            }
+        };
+
+        if redirects.len() == MAX_REDIRECTS {
+            return LinkCheck {
+                redirects,
+                outcome: Err(Failure {
+                    message: String::from("Too many redirects"),
+                    unreachable: false,
+                }),
+            };
+        }
+
+        // The location can be relative to the current URL
+        let next = match Url::parse(current.as_str()).and_then(|base| base.join(location.as_str()))
+        {
+            Ok(next) => String::from(next),
+            Err(_) => {
+                return LinkCheck {
+                    redirects,
+                    outcome: Err(Failure {
+                        message: format!("Invalid redirect to {location}"),
+                        unreachable: false,
+                    }),
+                };
+            }
+        };
+        redirects.push((status, next.clone()));
+        current = next;
+    }
+}
+
+/// The status and any Location header. HEAD is tried first since the page isn't needed, but some servers don't
+/// support it (or treat it differently, or drop the connection) so GET is used if it fails.
+/// A server that can't be reached is tried again in case it was a blip.
+fn request(agent: &Agent, url: &str) -> Result<(u16, Option<String>), Failure> {
+    match send(agent.head(url)) {
+        Ok((status, location)) if status < 400 => return Ok((status, location)),
+        _ => {}
+    }
+
+    match send(agent.get(url)) {
+        Err(failure) if failure.unreachable => {
+            thread::sleep(RETRY_DELAY);
+            send(agent.get(url))
+        }
+        get => get,
+    }
+}
+
+fn send(request: Request) -> Result<(u16, Option<String>), Failure> {
+    match request.call() {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => Ok((
+            response.status(),
+            response.header("location").map(String::from),
+        )),
+        Err(ureq::Error::Transport(err)) => Err(Failure {
+            unreachable: matches!(err.kind(), ErrorKind::Dns | ErrorKind::ConnectionFailed),
+            message: err.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::linkcheck::{Failure, LinkCheck};
+
+    fn status(status: u16) -> LinkCheck {
+        LinkCheck {
+            redirects: Vec::new(),
+            outcome: Ok(status),
+        }
+    }
+
+    #[test]
+    fn permanent_redirects() {
+        let moved = LinkCheck {
+            redirects: vec![
+                (301, String::from("https://b.com")),
+                (308, String::from("https://c.com")),
+            ],
+            outcome: Ok(200),
+        };
+        assert_eq!(moved.moved_to(), Some("https://c.com"));
+        assert_eq!(
+            moved.describe("https://a.com"),
+            "301 https://a.com -> https://c.com 200 (moved permanently)"
+        );
+
+        let temporary = LinkCheck {
+            redirects: vec![
+                (301, String::from("https://b.com")),
+                (302, String::from("https://c.com")),
+            ],
+            outcome: Ok(200),
+        };
+        assert_eq!(temporary.moved_to(), None);
+
+        let broken = LinkCheck {
+            redirects: vec![(301, String::from("https://b.com"))],
+            outcome: Ok(404),
+        };
+        assert!(broken.is_dead());
+        assert_eq!(broken.moved_to(), None);
+    }
+
+    #[test]
+    fn only_gone_links_are_dead() {
+        assert!(status(404).is_dead());
+        assert!(status(410).is_dead());
+
+        for status in [403, 429, 500, 503].map(status) {
+            assert!(!status.is_dead());
+            assert!(!status.is_working());
+        }
+        assert!(status(200).is_working());
+    }
+
+    #[test]
+    fn failures() {
+        let unreachable = LinkCheck {
+            redirects: Vec::new(),
+            outcome: Err(Failure {
+                message: String::from("Connection refused"),
+                unreachable: true,
+            }),
+        };
+        assert!(unreachable.is_dead());
+        assert_eq!(
+            unreachable.describe("https://a.com"),
+            "ERROR (Connection refused) https://a.com"
+        );
+
+        let timeout = LinkCheck {
+            redirects: Vec::new(),
+            outcome: Err(Failure {
+                message: String::from("timed out reading response"),
+                unreachable: false,
+            }),
+        };
+        assert!(!timeout.is_dead());
+        assert!(!timeout.is_working());
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::Result;
+use chrono::DateTime;
+use std::collections::HashMap;
+
+use crate::cli_output::utils::print_warning;
+use crate::config::Migrate;
+use crate::csv::{CsvChange, CsvLineReader, Line, Schema, format_added, parse_line, rewrite_csv};
+use crate::git::Git;
+
+pub fn migrate(migrate_opts: &Migrate, csv: &str) -> Result<()> {
+    let change = CsvChange::begin(csv, migrate_opts.git.commit)?;
+
+    let reader = CsvLineReader::new(csv)?;
+    let schema = Schema::latest();
+    // A file with every column that isn't escaped yet is escaped the next time it is rewritten
+    if reader.schema().columns() == schema.columns() {
+        print_warning("The CSV file already has every column");
+        return Ok(());
+    }
+
+    // Bookmarks without an added date get the date they were first committed. The history is still used with
+    // --no-commit. New columns are otherwise left empty (i.e. no notes and not archived).
+    let mut lines = reader.collect::<Result<Vec<Line>>>()?;
+    let found;
+    let history = match change.git() {
+        Some(git) => Some(git),
+        None => {
+            found = Git::find(csv);
+            found.as_ref()
+        }
+    };
+    let first_committed = match history {
+        Some(history) => first_committed(history, csv)?,
+        None => HashMap::new(),
+    };
+    for line in lines.iter_mut().filter(|line| line.added.is_none()) {
+        line.added = first_committed.get(&line.url).cloned();
+    }
+
+    rewrite_csv(csv, &schema, &lines)?;
+
+    change.finish(
+        format!("Migrate bookmarks to columns {}", schema.column_names()).as_str(),
+        "CSV file migrated",
+    )
+}
+
+/// URL -> time of the first commit with a bookmark for it, formatted for the ADDED column
+fn first_committed(git: &Git, csv: &str) -> Result<HashMap<String, String>> {
+    let mut first_committed = HashMap::new();
+
+    git.for_each_version(csv, |time, contents| {
+        let mut rows = contents.lines();
+        // Versions that can't be read (e.g. from before there was a header) are skipped
+        let Some(Ok(schema)) = rows.next().map(Schema::parse_header) else {
+            return;
+        };
+        let Some(added) = DateTime::from_timestamp(time, 0).map(format_added) else {
+            return;
+        };
+
+        for line in rows.filter_map(|row| parse_line(&schema, row).ok()) {
+            first_committed
+                .entry(line.url)
+                .or_insert_with(|| added.clone());
+        }
+    })?;
+
+    Ok(first_committed)
+}
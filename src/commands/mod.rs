@@ -1,3 +1,12 @@
 pub mod add;
+pub mod check;
+pub mod dedupe;
+pub mod edit;
+pub mod export;
+pub mod import;
+pub mod linkcheck;
+pub mod migrate;
+pub mod open;
+pub mod remove;
 pub mod search;
 pub mod tags;
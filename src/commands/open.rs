@@ -0,0 +1,180 @@
+use anyhow::{Context, Result, bail, ensure};
+use std::env;
+use std::io::{Read, Seek};
+use std::process::{self, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cli_output::search_result_output::SearchResultOutput;
+use crate::cli_output::utils::{print_warning, prompt};
+use crate::commands::search::{build_query, build_tag_expr, match_line};
+use crate::config::Open;
+use crate::csv::{ParseMode, read_lines};
+
+/// Command used to open bookmarks. Takes precedence over $BROWSER
+const ENV_OPENER: &str = "BOOKMARK_MANAGER_OPENER";
+
+/// How long to wait for the opener to fail (e.g. there is no browser). Openers like xdg-open exit once the browser has
+/// the URL, but a browser that wasn't already running only exits when it is closed, so it isn't waited for any longer.
+const OPENER_GRACE_PERIOD: Duration = Duration::from_millis(500);
+const OPENER_POLL_INTERVAL: Duration = Duration::from_millis(25);
+
+pub fn open(open_opts: &Open, csv: &str, parse_mode: ParseMode) -> Result<()> {
+    // Make sure either REGEX or at least one tag
+    ensure!(
+        open_opts.regex.is_some()
+            || !open_opts.filters.tags.is_empty()
+            || open_opts.filters.where_expr.is_some(),
+        "Either a REGEX, tag or tag expression is required"
+    );
+
+    let query = build_query(&open_opts.regex)?;
+    let tag_expr = build_tag_expr(&open_opts.filters.where_expr)?;
+
+    let mut out = SearchResultOutput::new();
+    let mut urls = Vec::new();
+
+    for line in read_lines(csv, parse_mode)? {
+        let url = line.url.clone();
+        if let Some(m) = match_line(&query, &open_opts.filters.tags, &tag_expr, line) {
+            out.add_matched_bookmark(m);
+            urls.push(url);
+        }
+    }
+
+    if urls.is_empty() {
+        bail!("No bookmarks matched");
+    }
+
+    // Only ask which bookmark to open when there is a choice to make
+    let selected = if urls.len() == 1 || open_opts.all {
+        urls
+    } else {
+        out.print_numbered();
+        let answer =
+            prompt(format!("Open which bookmark? [1-{}, a for all]", urls.len()).as_str())?;
+        match parse_choice(&answer, urls.len())? {
+            Some(Choice::All) => urls,
+            Some(Choice::One(index)) => vec![urls.swap_remove(index)],
+            None => {
+                print_warning("No bookmarks opened");
+                return Ok(());
+            }
+        }
+    };
+
+    let opener = opener(&open_opts.opener);
+    for url in &selected {
+        open_url(&opener, url)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Choice {
+    All,
+    /// Index (starting at 0) of the bookmark to open
+    One(usize),
+}
+
+/// Parse the user's pick from the numbered list. An empty answer cancels.
+fn parse_choice(answer: &str, count: usize) -> Result<Option<Choice>> {
+    match answer.to_lowercase().as_str() {
+        "" => Ok(None),
+        "a" | "all" => Ok(Some(Choice::All)),
+        number => match number.parse::<usize>() {
+            Ok(number) if (1..=count).contains(&number) => Ok(Some(Choice::One(number - 1))),
+            _ => bail!("Expected a number from 1 to {count} or a for all, not {answer}"),
+        },
+    }
+}
+
+/// The command (and any arguments) used to open a URL, in order of precedence:
+/// --opener, $BOOKMARK_MANAGER_OPENER, $BROWSER and then the platform's default
+fn opener(opener_opt: &Option<String>) -> Vec<String> {
+    let configured = opener_opt
+        .clone()
+        .or_else(|| env::var(ENV_OPENER).ok())
+        .or_else(|| env::var("BROWSER").ok())
+        .filter(|opener| !opener.trim().is_empty());
+
+    match configured {
+        Some(opener) => opener.split_whitespace().map(String::from).collect(),
+        None => default_opener(),
+    }
+}
+
+fn default_opener() -> Vec<String> {
+    let opener: &[&str] = if cfg!(target_os = "macos") {
+        &["open"]
+    } else if cfg!(windows) {
+        // Not cmd's start, which treats characters like & in the URL as commands
+        &["rundll32", "url.dll,FileProtocolHandler"]
+    } else {
+        &["xdg-open"]
+    };
+
+    opener.iter().map(|arg| String::from(*arg)).collect()
+}
+
+fn open_url(opener: &[String], url: &str) -> Result<()> {
+    let (program, args) = opener
+        .split_first()
+        .context("The opener command is empty")?;
+
+    // Kept in a file rather than a pipe, since a browser that keeps running could fill the pipe
+    let mut stderr =
+        tempfile::tempfile().context("Could not create a file for the opener's errors")?;
+    let mut child = process::Command::new(program)
+        .args(args)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(stderr.try_clone()?)
+        .spawn()
+        .with_context(|| format!("Could not run {program} to open {url}"))?;
+
+    let start = Instant::now();
+    while start.elapsed() < OPENER_GRACE_PERIOD {
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                let mut errors = String::new();
+                stderr.rewind()?;
+                stderr.read_to_string(&mut errors)?;
+                bail!(
+                    "{program} could not open {url} ({status}): {}",
+                    errors.trim()
+                );
+            }
+            break;
+        }
+        thread::sleep(OPENER_POLL_INTERVAL);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::open::{Choice, opener, parse_choice};
+
+    #[test]
+    fn choices() {
+        assert_eq!(parse_choice("", 3).unwrap(), None);
+        assert_eq!(parse_choice("A", 3).unwrap(), Some(Choice::All));
+        assert_eq!(parse_choice("1", 3).unwrap(), Some(Choice::One(0)));
+        assert_eq!(parse_choice("3", 3).unwrap(), Some(Choice::One(2)));
+        assert!(parse_choice("0", 3).is_err());
+        assert!(parse_choice("4", 3).is_err());
+        assert!(parse_choice("two", 3).is_err());
+    }
+
+    #[test]
+    fn opener_option_with_arguments() {
+        assert_eq!(
+            opener(&Some(String::from("firefox --new-tab"))),
+            vec!["firefox", "--new-tab"]
+        );
+    }
+}
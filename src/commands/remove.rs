@@ -0,0 +1,121 @@
+use anyhow::{Result, bail, ensure};
+use std::collections::HashSet;
+use url::Url;
+
+use crate::cli_output::search_result_output::SearchResultOutput;
+use crate::cli_output::utils::{confirm, print_warning};
+use crate::commands::search::{build_query, build_tag_expr, match_line};
+use crate::config::Remove;
+use crate::csv::{CsvChange, CsvLineReader, Line, ParseMode, read_lines, rewrite_csv};
+
+pub fn remove(remove_opts: &Remove, csv: &str) -> Result<()> {
+    // Make sure either URL/REGEX or at least one tag so everything isn't removed by accident
+    ensure!(
+        remove_opts.regex.is_some()
+            || !remove_opts.filters.tags.is_empty()
+            || remove_opts.filters.where_expr.is_some(),
+        "Either a URL, REGEX, tag or tag expression is required"
+    );
+
+    // The CSV isn't locked while waiting for confirmation, so only the URLs to remove are kept
+    let (out, urls) = matching(remove_opts, read_lines(csv, ParseMode::Strict)?)?;
+    if urls.is_empty() {
+        bail!("No bookmarks matched");
+    }
+
+    out.print();
+
+    if !remove_opts.yes && !confirm(format!("Remove {} bookmark(s)?", urls.len()).as_str())? {
+        print_warning("No bookmarks removed");
+        return Ok(());
+    }
+
+    let change = CsvChange::begin(csv, remove_opts.git.commit)?;
+
+    // Read again since the file may have changed while waiting for confirmation
+    let reader = CsvLineReader::new(csv)?;
+    let schema = reader.schema().clone();
+    let (removed, kept): (Vec<Line>, Vec<Line>) = reader
+        .collect::<Result<Vec<Line>>>()?
+        .into_iter()
+        .partition(|line| urls.contains(&line.url));
+
+    if removed.len() < urls.len() {
+        print_warning(
+            format!(
+                "{} bookmark(s) were already removed",
+                urls.len() - removed.len()
+            )
+            .as_str(),
+        );
+    }
+    if removed.is_empty() {
+        print_warning("No bookmarks removed");
+        return Ok(());
+    }
+
+    rewrite_csv(csv, &schema, &kept)?;
+
+    change.finish(
+        commit_message(&removed).as_str(),
+        format!("{} bookmark(s) removed", removed.len()).as_str(),
+    )
+}
+
+/// The matched bookmarks to show and their URLs
+fn matching(
+    remove_opts: &Remove,
+    lines: Vec<Line>,
+) -> Result<(SearchResultOutput, HashSet<String>)> {
+    // A URL is only matched exactly, never as a REGEX.
+    // Otherwise, removing https://google.com would also remove https://google.com/maps
+    let exact_url = remove_opts
+        .regex
+        .as_ref()
+        .filter(|arg| Url::parse(arg).is_ok_and(|url| url.has_host()));
+    if let Some(url) = exact_url {
+        ensure!(
+            lines.iter().any(|line| line.url == *url),
+            "No bookmark with URL {url}"
+        );
+    }
+    let query = match exact_url {
+        Some(_) => None,
+        None => build_query(&remove_opts.regex)?,
+    };
+    let tag_expr = build_tag_expr(&remove_opts.filters.where_expr)?;
+
+    let mut out = SearchResultOutput::new();
+    let mut urls = HashSet::new();
+
+    for line in lines {
+        let is_url_match = exact_url.is_none_or(|url| line.url == *url);
+        if !is_url_match {
+            continue;
+        }
+
+        let url = line.url.clone();
+        if let Some(m) = match_line(&query, &remove_opts.filters.tags, &tag_expr, line) {
+            out.add_matched_bookmark(m);
+            urls.insert(url);
+        }
+    }
+
+    Ok((out, urls))
+}
+
+/// List the removed URLs in the commit message
+fn commit_message(removed: &[Line]) -> String {
+    match removed {
+        [line] => format!("Remove bookmark for {}", line.url),
+        _ => format!(
+            "Remove {} bookmarks\n\n{}",
+            removed.len(),
+            removed
+                .iter()
+                .map(|line| line.url.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n")
+        ),
+    }
+}
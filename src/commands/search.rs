@@ -1,77 +1,197 @@
-use anyhow::{Context, Result, ensure};
-use regex::{Regex, RegexBuilder};
+use anyhow::{Result, ensure};
+use chrono::DateTime;
 
+use crate::canonical_url::host_range;
 use crate::cli_output::search_result_output::{MatchedBookmark, SearchResultOutput, TextPart};
-use crate::config::Search;
-use crate::csv::{CsvLineReader, Line};
-use std::collections::HashMap;
-
-pub fn search(search_opts: &Search, csv: &str) -> Result<()> {
+use crate::config::{OutputFormat, Search, SortKey};
+use crate::csv::{Line, ParseMode, read_lines};
+use crate::fuzzy;
+use crate::query::{Query, QueryMatch};
+use crate::tag_expr::{TagExpr, lookup_key};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::ops::Range;
+
+pub fn search(search_opts: &Search, csv: &str, parse_mode: ParseMode) -> Result<()> {
     // Make sure either REGEX or at least one tag
     ensure!(
-        search_opts.regex.is_some() || !search_opts.tags.is_empty(),
-        "Either a REGEX or a tag is required"
+        search_opts.regex.is_some()
+            || !search_opts.filters.tags.is_empty()
+            || search_opts.filters.where_expr.is_some(),
+        "Either a REGEX, tag or tag expression is required"
     );
 
-    // Only compile the regex once
-    let re = match &search_opts.regex {
-        Some(regex) => Some(build_regex(regex.as_str())?),
-        None => None,
-    };
+    ensure!(
+        !search_opts.fuzzy || search_opts.regex.is_some(),
+        "--fuzzy needs text to search for"
+    );
 
-    let mut out = SearchResultOutput::new();
-    let reader = CsvLineReader::new(csv)?;
+    ensure!(
+        !search_opts.spans
+            || matches!(search_opts.format, OutputFormat::Json | OutputFormat::Jsonl),
+        "--spans only works with --format json or jsonl"
+    );
 
-    for line in reader {
-        if let Some(m) = match_line(&re, &search_opts.tags, line?) {
-            out.add_matched_bookmark(m);
+    // Only compile the tag expression once
+    let tag_expr = build_tag_expr(&search_opts.filters.where_expr)?;
+
+    let mut matches = Vec::new();
+    let lines = read_lines(csv, parse_mode)?;
+
+    match (&search_opts.regex, search_opts.fuzzy) {
+        (Some(text), true) => {
+            for line in lines {
+                // The tag filters still have to match exactly
+                if match_line(&None, &search_opts.filters.tags, &tag_expr, line.clone()).is_none() {
+                    continue;
+                }
+                if let Some((score, m)) = fuzzy::find(text, &line) {
+                    let bookmark = highlight(line.clone(), m);
+                    matches.push(SearchMatch {
+                        line,
+                        score,
+                        bookmark,
+                    });
+                }
+            }
+        }
+        _ => {
+            // Only compile the query once
+            let query = build_query(&search_opts.regex)?;
+
+            for line in lines {
+                if let Some(bookmark) =
+                    match_line(&query, &search_opts.filters.tags, &tag_expr, line.clone())
+                {
+                    // The more of the bookmark the query matched, the more relevant it is
+                    let score = matched_len(&bookmark) as f64;
+                    matches.push(SearchMatch {
+                        line,
+                        score,
+                        bookmark,
+                    });
+                }
+            }
         }
     }
 
+    // Fuzzy results are always ranked, otherwise the file order is kept unless asked for
+    let sort = match (search_opts.sort, search_opts.fuzzy) {
+        (None, true) => Some(SortKey::Relevance),
+        (sort, _) => sort,
+    };
+    if let Some(sort) = sort {
+        sort_matches(&mut matches, sort);
+    }
+    if search_opts.reverse {
+        matches.reverse();
+    }
+
+    let mut out = SearchResultOutput::new();
+    for m in matches
+        .into_iter()
+        .skip(search_opts.offset)
+        .take(search_opts.limit.unwrap_or(usize::MAX))
+    {
+        out.add_matched_bookmark(m.bookmark);
+    }
+
     // For formatting purposes the output is stored in memory until the search is complete. Print to console now
-    out.print();
+    out.print_as(search_opts.format, search_opts.spans)?;
 
     Ok(())
 }
 
-fn build_regex(regex: &str) -> Result<Regex> {
-    let re = RegexBuilder::new(regex)
-        .case_insensitive(true)
-        .build()
-        .context("Invalid REGEX")?;
+struct SearchMatch {
+    line: Line,
+    /// Higher is more relevant
+    score: f64,
+    bookmark: MatchedBookmark,
+}
+
+/// Sorts are stable so bookmarks which are equal stay in file order
+fn sort_matches(matches: &mut [SearchMatch], sort: SortKey) {
+    match sort {
+        SortKey::Url => matches.sort_by_cached_key(|m| m.line.url.to_lowercase()),
+        SortKey::Description => matches.sort_by_cached_key(|m| m.line.description.to_lowercase()),
+        SortKey::Domain => matches.sort_by_cached_key(|m| {
+            let host = host_range(&m.line.url).map_or("", |host| &m.line.url[host]);
+            (host.to_lowercase(), m.line.url.to_lowercase())
+        }),
+        // Reversed so the newest is first. None (no date) sorts before any date, so reversed it is last
+        SortKey::Added => matches.sort_by_cached_key(|m| {
+            Reverse(
+                m.line
+                    .added
+                    .as_ref()
+                    .and_then(|added| DateTime::parse_from_rfc3339(added).ok()),
+            )
+        }),
+        SortKey::Relevance => matches.sort_by(|a, b| b.score.total_cmp(&a.score)),
+    }
+}
+
+/// Number of characters highlighted in the URL and description
+fn matched_len(bookmark: &MatchedBookmark) -> usize {
+    bookmark
+        .url
+        .iter()
+        .chain(bookmark.description.iter())
+        .filter(|part| matches!(part, TextPart::MatchedText(_)))
+        .map(|part| part.len())
+        .sum()
+}
+
+/// Parse the REGEX or field-qualified query
+pub fn build_query(query: &Option<String>) -> Result<Option<Query>> {
+    query.as_deref().map(Query::parse).transpose()
+}
 
-    Ok(re)
+/// Parse the --where tag expression
+pub fn build_tag_expr(expr: &Option<String>) -> Result<Option<TagExpr>> {
+    expr.as_deref().map(TagExpr::parse).transpose()
 }
 
-fn match_line(re: &Option<Regex>, search_tags: &[String], line: Line) -> Option<MatchedBookmark> {
+pub fn match_line(
+    query: &Option<Query>,
+    search_tags: &[String],
+    tag_expr: &Option<TagExpr>,
+    line: Line,
+) -> Option<MatchedBookmark> {
     let url = line.url.as_str();
     let description = line.description.as_str();
 
+    // Tags with a / are a hierarchy, so a tag also matches each of its ancestors. e.g. lang/rust matches lang
     let tag_lookup = line
         .tags
         .iter()
-        .map(|tag| (tag.to_lowercase(), 1))
-        .collect::<HashMap<String, _>>();
+        .flat_map(|tag| tag_and_ancestors(&tag.to_lowercase()))
+        .collect::<HashSet<String>>();
 
     // Make sure the line has all tags
     // https://stackoverflow.com/a/64227550
     if !search_tags
         .iter()
-        .all(|tag| tag_lookup.contains_key(&tag.to_lowercase()))
+        .all(|tag| tag_lookup.contains(&lookup_key(tag)))
     {
         return None;
     }
 
-    // If there are tags, they matched. Then, if there is a regex, it must match as well
-    if let Some(regex) = &re {
-        let (url_is_match, url) = wrap_matches(regex, url);
-        let (desc_is_match, description) = wrap_matches(regex, description);
+    // The tag expression is in addition to any tags
+    if tag_expr
+        .as_ref()
+        .is_some_and(|expr| !expr.eval(&tag_lookup))
+    {
+        return None;
+    }
 
-        if url_is_match || desc_is_match {
-            return Some(MatchedBookmark::new(url, description, line.tags));
+    // If there are tags, they matched. Then, if there is a query, it must match as well
+    if let Some(query) = &query {
+        if let Some(m) = query.find(url, description, &tag_lookup) {
+            return Some(highlight(line, m));
         }
     }
-    // There is no regex, there are tags and they matched
+    // There is no query, there are tags and they matched
     else {
         return Some(MatchedBookmark::new_tags_only(url, description, line.tags));
     }
@@ -79,29 +199,42 @@ fn match_line(re: &Option<Regex>, search_tags: &[String], line: Line) -> Option<
     None
 }
 
+/// The tag and every level above it. e.g. lang/rust/async -> lang, lang/rust, lang/rust/async
+fn tag_and_ancestors(tag: &str) -> Vec<String> {
+    let mut tags = tag
+        .match_indices('/')
+        .map(|(index, _)| String::from(&tag[..index]))
+        .filter(|ancestor| !ancestor.is_empty() && !ancestor.ends_with('/'))
+        .collect::<Vec<String>>();
+    tags.push(String::from(tag.trim_end_matches('/')));
+
+    tags
+}
+
+fn highlight(line: Line, m: QueryMatch) -> MatchedBookmark {
+    MatchedBookmark::new(
+        wrap_matches(&line.url, &m.url),
+        wrap_matches(&line.description, &m.description),
+        line.tags,
+    )
+}
+
 // https://stackoverflow.com/a/56923739
-/// Return if the regex was matched, the display string which will include any highlighting,
-/// and the number of characters in the original string.
-fn wrap_matches(re: &Regex, text: &str) -> (bool, Vec<TextPart>) {
-    let mut found: bool = false;
+/// Split the text into parts so the matched ranges (sorted and not overlapping) can be highlighted
+fn wrap_matches(text: &str, ranges: &[Range<usize>]) -> Vec<TextPart> {
     let mut parts: Vec<TextPart> = Vec::new();
 
     let mut last = 0;
-    for mat in re.find_iter(text) {
-        found = true;
-
-        let start = mat.start();
-        let end = mat.end();
-
+    for range in ranges {
         // Add everything up to the match
-        if last != start {
-            parts.push(TextPart::Text(String::from(&text[last..start])));
+        if last != range.start {
+            parts.push(TextPart::Text(String::from(&text[last..range.start])));
         }
 
         // Add the match
-        parts.push(TextPart::MatchedText(String::from(&text[start..end])));
+        parts.push(TextPart::MatchedText(String::from(&text[range.clone()])));
 
-        last = end;
+        last = range.end;
     }
 
     // Add any remaining text after last match
@@ -110,26 +243,25 @@ fn wrap_matches(re: &Regex, text: &str) -> (bool, Vec<TextPart>) {
         parts.push(TextPart::Text(String::from(&text[last..])));
     }
 
-    (found, parts)
+    parts
 }
 
 #[cfg(test)]
 mod tests {
     use crate::cli_output::search_result_output::{MatchedBookmark, TextPart};
-    use crate::commands::search::{build_regex, match_line};
+    use crate::commands::search::{SearchMatch, match_line, sort_matches};
+    use crate::config::SortKey;
     use crate::csv::Line;
-    use regex::Regex;
+    use crate::query::Query;
+    use crate::tag_expr::TagExpr;
 
     #[test]
     fn no_match() {
         let m = match_line(
             &regex_from_str("what"),
             &Vec::new(),
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("more than one"),
-                tags: Vec::new(),
-            },
+            &None,
+            Line::new("https://google.com", "more than one", &[]),
         );
 
         assert!(m.is_none());
@@ -140,11 +272,8 @@ mod tests {
         let m = match_line(
             &regex_from_str("Hi"),
             &Vec::new(),
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("Hi there"),
-                tags: Vec::new(),
-            },
+            &None,
+            Line::new("https://google.com", "Hi there", &[]),
         );
 
         single_matched_description(m, "Hi");
@@ -155,11 +284,8 @@ mod tests {
         let m = match_line(
             &regex_from_str("hi"),
             &Vec::new(),
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("Hi there"),
-                tags: Vec::new(),
-            },
+            &None,
+            Line::new("https://google.com", "Hi there", &[]),
         );
 
         single_matched_description(m, "Hi");
@@ -170,11 +296,8 @@ mod tests {
         let m = match_line(
             &regex_from_str("hi There"),
             &Vec::new(),
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("Hi there"),
-                tags: Vec::new(),
-            },
+            &None,
+            Line::new("https://google.com", "Hi there", &[]),
         );
 
         single_matched_description(m, "Hi there");
@@ -185,11 +308,8 @@ mod tests {
         let m = match_line(
             &regex_from_str("t.e"),
             &Vec::new(),
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("Hi there"),
-                tags: Vec::new(),
-            },
+            &None,
+            Line::new("https://google.com", "Hi there", &[]),
         );
 
         single_matched_description(m, "the");
@@ -200,11 +320,8 @@ mod tests {
         let m = match_line(
             &regex_from_str("more than"),
             &Vec::new(),
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("more than one"),
-                tags: Vec::new(),
-            },
+            &None,
+            Line::new("https://google.com", "more than one", &[]),
         );
 
         single_matched_description(m, "more than");
@@ -216,11 +333,8 @@ mod tests {
         let m = match_line(
             &regex_from_str("google"),
             &Vec::new(),
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("more than one"),
-                tags: Vec::new(),
-            },
+            &None,
+            Line::new("https://google.com", "more than one", &[]),
         );
 
         single_matched_url(m, "google");
@@ -231,11 +345,8 @@ mod tests {
         let m = match_line(
             &regex_from_str("g..g"),
             &Vec::new(),
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("more than one"),
-                tags: Vec::new(),
-            },
+            &None,
+            Line::new("https://google.com", "more than one", &[]),
         );
 
         single_matched_url(m, "goog");
@@ -246,11 +357,8 @@ mod tests {
         let m = match_line(
             &None,
             &[String::from("Tag1")],
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("more than one"),
-                tags: vec![String::from("Tag1")],
-            },
+            &None,
+            Line::new("https://google.com", "more than one", &["Tag1"]),
         );
 
         assert!(m.is_some());
@@ -264,11 +372,8 @@ mod tests {
         let m = match_line(
             &None,
             &[String::from("tag1")],
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("more than one"),
-                tags: vec![String::from("Tag1")],
-            },
+            &None,
+            Line::new("https://google.com", "more than one", &["Tag1"]),
         );
 
         assert!(m.is_some());
@@ -282,11 +387,8 @@ mod tests {
         let m = match_line(
             &regex_from_str("g..g"),
             &[String::from("tag1")],
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("more than one"),
-                tags: vec![String::from("Tag1")],
-            },
+            &None,
+            Line::new("https://google.com", "more than one", &["Tag1"]),
         );
 
         single_matched_url(m, "goog");
@@ -297,11 +399,8 @@ mod tests {
         let m = match_line(
             &regex_from_str("g..g"),
             &[String::from("what")],
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("more than one"),
-                tags: vec![String::from("Tag1")],
-            },
+            &None,
+            Line::new("https://google.com", "more than one", &["Tag1"]),
         );
 
         assert!(m.is_none());
@@ -312,15 +411,12 @@ mod tests {
         let m = match_line(
             &None,
             &[String::from("Tag1"), String::from("Tag2")],
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("more than one"),
-                tags: vec![
-                    String::from("Tag1"),
-                    String::from("Tag2"),
-                    String::from("Tag3"),
-                ],
-            },
+            &None,
+            Line::new(
+                "https://google.com",
+                "more than one",
+                &["Tag1", "Tag2", "Tag3"],
+            ),
         );
 
         assert!(m.is_some());
@@ -334,15 +430,12 @@ mod tests {
         let m = match_line(
             &None,
             &[String::from("Tag1 a doodle do")],
-            Line {
-                url: String::from("https://google.com"),
-                description: String::from("more than one"),
-                tags: vec![
-                    String::from("Tag1 a Doodle Do"),
-                    String::from("Tag2"),
-                    String::from("Tag3"),
-                ],
-            },
+            &None,
+            Line::new(
+                "https://google.com",
+                "more than one",
+                &["Tag1 a Doodle Do", "Tag2", "Tag3"],
+            ),
         );
 
         assert!(m.is_some());
@@ -375,7 +468,100 @@ mod tests {
             .collect::<Vec<TextPart>>()
     }
 
-    fn regex_from_str(regex: &str) -> Option<Regex> {
-        Some(build_regex(regex).unwrap())
+    #[test]
+    fn tag_expression() {
+        let line = Line::new("https://google.com", "more than one", &["Docs", "Internal"]);
+
+        let expr = Some(TagExpr::parse("docs & !internal").unwrap());
+        assert!(match_line(&None, &Vec::new(), &expr, line.clone()).is_none());
+
+        let expr = Some(TagExpr::parse("rust | docs").unwrap());
+        assert!(match_line(&None, &Vec::new(), &expr, line.clone()).is_some());
+        assert!(match_line(&None, &[String::from("missing")], &expr, line).is_none());
+    }
+
+    fn sorted_urls(lines: Vec<Line>, sort: SortKey) -> Vec<String> {
+        let mut matches = lines
+            .into_iter()
+            .map(|line| SearchMatch {
+                bookmark: MatchedBookmark::new_tags_only(&line.url, &line.description, Vec::new()),
+                line,
+                score: 0.0,
+            })
+            .collect::<Vec<SearchMatch>>();
+
+        sort_matches(&mut matches, sort);
+        matches.into_iter().map(|m| m.line.url).collect()
+    }
+
+    fn added_line(url: &str, added: Option<&str>) -> Line {
+        Line {
+            added: added.map(String::from),
+            ..Line::new(url, "", &[])
+        }
+    }
+
+    #[test]
+    fn sort_by_domain() {
+        let lines = vec![
+            added_line("https://www.github.com/b", None),
+            added_line("https://Bing.com", None),
+            added_line("https://www.github.com/a", None),
+        ];
+
+        assert_eq!(
+            sorted_urls(lines, SortKey::Domain),
+            vec![
+                "https://Bing.com",
+                "https://www.github.com/a",
+                "https://www.github.com/b"
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_by_added_newest_first() {
+        let lines = vec![
+            added_line("https://none.com", None),
+            added_line("https://old.com", Some("2021-07-22T10:46:41Z")),
+            // Later than the UTC date above once the offset is taken into account
+            added_line("https://new.com", Some("2021-07-22T08:00:00-04:00")),
+        ];
+
+        assert_eq!(
+            sorted_urls(lines, SortKey::Added),
+            vec!["https://new.com", "https://old.com", "https://none.com"]
+        );
+    }
+
+    #[test]
+    fn hierarchical_tags_match_descendants() {
+        let line = Line::new("https://tokio.rs", "Tokio", &["Lang/Rust/Async"]);
+
+        for tag in ["lang", "lang/rust", "LANG/RUST/", "lang/rust/async"] {
+            assert!(
+                match_line(&None, &[String::from(tag)], &None, line.clone()).is_some(),
+                "{tag}"
+            );
+            // The same as a tag expression
+            let expr = Some(TagExpr::parse(tag).unwrap());
+            assert!(
+                match_line(&None, &Vec::new(), &expr, line.clone()).is_some(),
+                "{tag}"
+            );
+        }
+        for tag in ["rust", "lang/ru", "lang/rust/async/more"] {
+            assert!(
+                match_line(&None, &[String::from(tag)], &None, line.clone()).is_none(),
+                "{tag}"
+            );
+        }
+
+        let expr = Some(TagExpr::parse("lang/rust & !lang/go").unwrap());
+        assert!(match_line(&None, &Vec::new(), &expr, line).is_some());
+    }
+
+    fn regex_from_str(regex: &str) -> Option<Query> {
+        Some(Query::parse(regex).unwrap())
     }
 }
@@ -1,46 +1,432 @@
-use crate::config::Tags;
-
-use crate::csv::CsvLineReader;
-use anyhow::Result;
-use std::collections::HashMap;
-
-pub fn tags(tags_opts: &Tags, csv: &str) -> Result<()> {
-    let map = get_tags(csv)?;
-
-    let mut keys = map.keys().collect::<Vec<&String>>();
-    keys.sort(); // Already lowercase
-    for key in keys {
-        let value = map.get(key.as_str());
-        if let Some(tags) = value {
-            let mut tags = tags.clone();
-            tags.sort();
-            if tags_opts.machine {
-                tags.iter().for_each(|tag| println!("{tag}"));
-            } else {
-                println!("{}", tags.join(", "));
-            }
+use crate::cli_output::utils::print_warning;
+use crate::config::{TagSort, Tags, TagsAction, TagsMerge, TagsNormalize, TagsRename};
+
+use crate::csv::{CsvChange, CsvLineReader, Line, ParseMode, read_lines, rewrite_csv};
+use crate::settings::{Settings, TagNormalizer};
+use anyhow::{Result, ensure};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::slice;
+
+pub fn tags(tags_opts: &Tags, csv: &str, parse_mode: ParseMode) -> Result<()> {
+    match &tags_opts.action {
+        Some(TagsAction::Rename(rename_opts)) => rename(rename_opts, csv),
+        Some(TagsAction::Merge(merge_opts)) => merge(merge_opts, csv),
+        Some(TagsAction::Normalize(normalize_opts)) => normalize(normalize_opts, csv),
+        None => list(tags_opts, csv, parse_mode),
+    }
+}
+
+fn list(tags_opts: &Tags, csv: &str, parse_mode: ParseMode) -> Result<()> {
+    if tags_opts.tree {
+        print_tree(&get_tree(csv, parse_mode)?, tags_opts.sort, 0);
+        return Ok(());
+    }
+
+    let map = get_tags(csv, parse_mode)?;
+
+    let mut usages = map
+        .into_iter()
+        .filter(|(_, usage)| {
+            tags_opts
+                .unused_below
+                .is_none_or(|below| usage.count < below)
+        })
+        .collect::<Vec<(String, TagUsage)>>();
+
+    usages.sort_by(|(a_key, a), (b_key, b)| match tags_opts.sort {
+        TagSort::Name => a_key.cmp(b_key), // Already lowercase
+        TagSort::Count => b.count.cmp(&a.count).then(a_key.cmp(b_key)),
+    });
+    for (_, usage) in usages.iter_mut() {
+        usage.tags.sort();
+    }
+
+    if tags_opts.json {
+        let json = usages
+            .iter()
+            .map(|(key, usage)| JsonTag {
+                name: key,
+                variants: &usage.tags,
+                count: usage.count,
+            })
+            .collect::<Vec<JsonTag>>();
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+
+    let width = usages
+        .iter()
+        .map(|(_, usage)| usage.count.to_string().len())
+        .max()
+        .unwrap_or_default();
+
+    for (_, usage) in &usages {
+        match (tags_opts.machine, tags_opts.count) {
+            (true, true) => usage
+                .tags
+                .iter()
+                .for_each(|tag| println!("{}\t{tag}", usage.count)),
+            (true, false) => usage.tags.iter().for_each(|tag| println!("{tag}")),
+            (false, true) => println!("{:>width$}  {}", usage.count, usage.tags.join(", ")),
+            (false, false) => println!("{}", usage.tags.join(", ")),
         }
     }
 
     Ok(())
 }
 
-fn get_tags(csv: &str) -> Result<HashMap<String, Vec<String>>> {
-    let reader = CsvLineReader::new(csv)?;
+/// Every case of a tag and how many bookmarks have it
+#[derive(Debug, Default, PartialEq, Eq)]
+struct TagUsage {
+    tags: Vec<String>,
+    count: usize,
+}
 
-    let mut map = HashMap::new();
+/// Machine-readable tag usage
+#[derive(Serialize)]
+struct JsonTag<'a> {
+    /// Lowercase
+    name: &'a str,
+    /// Every case of the tag used by bookmarks
+    variants: &'a [String],
+    /// Number of bookmarks with the tag (in any case)
+    count: usize,
+}
+
+/// Tag usage by lowercase tag
+fn get_tags(csv: &str, parse_mode: ParseMode) -> Result<HashMap<String, TagUsage>> {
+    let mut map: HashMap<String, TagUsage> = HashMap::new();
 
-    for line in reader {
-        let line = line?;
+    for line in read_lines(csv, parse_mode)? {
+        // A bookmark is only counted once even if it has the same tag in different cases
+        let mut counted = HashSet::new();
 
         for tag in line.tags {
             let key = tag.to_lowercase();
-            let entry = map.entry(key).or_insert_with(Vec::new);
-            if !entry.contains(&tag) {
-                entry.push(tag);
+            let entry = map.entry(key.clone()).or_default();
+            if counted.insert(key) {
+                entry.count += 1;
+            }
+            if !entry.tags.contains(&tag) {
+                entry.tags.push(tag);
             }
         }
     }
 
     Ok(map)
 }
+
+/// A level of the tag hierarchy
+#[derive(Debug, Default)]
+struct TagNode {
+    /// Name of this level (in the case it was first seen)
+    name: String,
+    /// Number of bookmarks with this tag or a tag below it
+    count: usize,
+    /// By lowercase name
+    children: BTreeMap<String, TagNode>,
+}
+
+/// Tags split into their levels on /
+fn get_tree(csv: &str, parse_mode: ParseMode) -> Result<TagNode> {
+    let mut root = TagNode::default();
+
+    for line in read_lines(csv, parse_mode)? {
+        // A bookmark is only counted once per level even if several of its tags are below it
+        let mut counted = HashSet::new();
+
+        for tag in line.tags {
+            let mut node = &mut root;
+            let mut path = String::new();
+
+            for level in tag.split('/').filter(|level| !level.is_empty()) {
+                path.push_str(level.to_lowercase().as_str());
+                path.push('/');
+
+                node = node
+                    .children
+                    .entry(level.to_lowercase())
+                    .or_insert_with(|| TagNode {
+                        name: String::from(level),
+                        ..Default::default()
+                    });
+                if counted.insert(path.clone()) {
+                    node.count += 1;
+                }
+            }
+        }
+    }
+
+    Ok(root)
+}
+
+fn print_tree(node: &TagNode, sort: TagSort, depth: usize) {
+    let mut children = node.children.values().collect::<Vec<&TagNode>>();
+    // Already sorted by name
+    if sort == TagSort::Count {
+        children.sort_by_key(|child| Reverse(child.count));
+    }
+
+    for child in children {
+        println!("{}{} ({})", "  ".repeat(depth), child.name, child.count);
+        print_tree(child, sort, depth + 1);
+    }
+}
+
+fn rename(rename_opts: &TagsRename, csv: &str) -> Result<()> {
+    replace_everywhere(
+        csv,
+        slice::from_ref(&rename_opts.old),
+        &rename_opts.new,
+        rename_opts.ignore_case,
+        rename_opts.git.commit,
+        format!("Rename tag {} to {}", rename_opts.old, rename_opts.new).as_str(),
+    )
+}
+
+fn merge(merge_opts: &TagsMerge, csv: &str) -> Result<()> {
+    replace_everywhere(
+        csv,
+        &merge_opts.tags,
+        &merge_opts.into,
+        merge_opts.ignore_case,
+        merge_opts.git.commit,
+        format!(
+            "Merge tags {} into {}",
+            merge_opts.tags.join(", "),
+            merge_opts.into
+        )
+        .as_str(),
+    )
+}
+
+/// Apply the tag policy to every bookmark
+fn normalize(normalize_opts: &TagsNormalize, csv: &str) -> Result<()> {
+    rewrite_tags(
+        csv,
+        normalize_opts.git.commit,
+        "Normalize tags",
+        |line, normalizer| {
+            let tags = normalizer.normalize(&line.tags);
+            let changed = tags != line.tags;
+            line.tags = tags;
+            changed
+        },
+    )
+}
+
+/// Replace the tags on every bookmark that has them
+fn replace_everywhere(
+    csv: &str,
+    from: &[String],
+    into: &str,
+    ignore_case: bool,
+    commit: bool,
+    commit_msg: &str,
+) -> Result<()> {
+    ensure!(!into.trim().is_empty(), "The new tag can't be empty");
+
+    rewrite_tags(csv, commit, commit_msg, |line, normalizer| {
+        let into = normalizer.normalize_explicit(into);
+        replace_tags(line, from, &into, ignore_case)
+    })
+}
+
+/// Change the tags of every bookmark and commit the change as a single commit.
+/// The change returns if the line changed.
+fn rewrite_tags(
+    csv: &str,
+    commit: bool,
+    commit_msg: &str,
+    mut change: impl FnMut(&mut Line, &mut TagNormalizer) -> bool,
+) -> Result<()> {
+    let csv_change = CsvChange::begin(csv, commit)?;
+
+    let reader = CsvLineReader::new(csv)?;
+    let schema = reader.schema().clone();
+    let mut lines = reader.collect::<Result<Vec<Line>>>()?;
+
+    let settings = Settings::load(csv)?;
+    let mut normalizer = TagNormalizer::new(&settings.tags).with_existing(&lines);
+
+    let mut changed = 0;
+    for line in lines.iter_mut() {
+        if change(line, &mut normalizer) {
+            changed += 1;
+        }
+    }
+
+    if changed == 0 {
+        print_warning("No bookmarks changed");
+        return Ok(());
+    }
+
+    rewrite_csv(csv, &schema, &lines)?;
+
+    let msg = format!("{changed} bookmark(s) changed");
+    csv_change.finish(format!("{commit_msg}\n\n{msg}").as_str(), msg.as_str())
+}
+
+/// Replace any of the tags with the new tag (in the position of the first one). Tags that end up the same (ignoring
+/// case) are only kept once, in the case of the new tag. Returns if the line changed.
+fn replace_tags(line: &mut Line, from: &[String], into: &str, ignore_case: bool) -> bool {
+    let is_match = |tag: &str, other: &str| match ignore_case {
+        true => tag.to_lowercase() == other.to_lowercase(),
+        false => tag == other,
+    };
+
+    if !line
+        .tags
+        .iter()
+        .any(|tag| from.iter().any(|from| is_match(tag, from)))
+    {
+        return false;
+    }
+
+    let mut tags: Vec<String> = Vec::with_capacity(line.tags.len());
+    for tag in &line.tags {
+        let tag = match from.iter().any(|from| is_match(tag, from)) {
+            true => into,
+            false => tag.as_str(),
+        };
+        // Don't end up with the new tag twice if the bookmark already had it in any case
+        match tags
+            .iter_mut()
+            .find(|existing| existing.to_lowercase() == tag.to_lowercase())
+        {
+            Some(existing) if tag == into => *existing = String::from(into),
+            Some(_) => {}
+            None => tags.push(String::from(tag)),
+        }
+    }
+
+    let changed = tags != line.tags;
+    line.tags = tags;
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tags::{TagUsage, get_tags, replace_everywhere, replace_tags};
+    use crate::csv::{Line, ParseMode, read_lines};
+    use crate::settings::settings_path;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn line(tags: &[&str]) -> Line {
+        Line::new("https://google.com", "Google", tags)
+    }
+
+    fn replace(tags: &[&str], from: &[&str], into: &str, ignore_case: bool) -> Option<Vec<String>> {
+        let mut line = line(tags);
+        let from = from
+            .iter()
+            .map(|tag| String::from(*tag))
+            .collect::<Vec<String>>();
+        replace_tags(&mut line, &from, into, ignore_case).then_some(line.tags)
+    }
+
+    #[test]
+    fn rename() {
+        assert_eq!(
+            replace(&["a", "rust"], &["rust"], "Rust", false),
+            Some(vec![String::from("a"), String::from("Rust")])
+        );
+        assert_eq!(replace(&["a", "RUST"], &["rust"], "Rust", false), None);
+        assert_eq!(
+            replace(&["a", "RUST"], &["rust"], "Rust", true),
+            Some(vec![String::from("a"), String::from("Rust")])
+        );
+        // Already correct
+        assert_eq!(replace(&["Rust"], &["rust"], "Rust", true), None);
+    }
+
+    #[test]
+    fn merge_without_duplicates() {
+        assert_eq!(
+            replace(&["golang", "b", "go", "c"], &["golang", "go"], "Go", false),
+            Some(vec![
+                String::from("Go"),
+                String::from("b"),
+                String::from("c")
+            ])
+        );
+        assert_eq!(
+            replace(&["Go", "golang"], &["golang"], "Go", false),
+            Some(vec![String::from("Go")])
+        );
+        // The bookmark already had the new tag in another case
+        assert_eq!(
+            replace(&["go", "golang"], &["golang"], "Go", false),
+            Some(vec![String::from("Go")])
+        );
+    }
+
+    #[test]
+    fn case_only_rename_with_first_seen_policy() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        fs::write(
+            &csv,
+            "URL|DESCRIPTION|TAGS\nhttps://a.com|A|rust,web\nhttps://b.com|B|rust\n",
+        )
+        .unwrap();
+        fs::write(
+            settings_path(csv.to_str().unwrap()),
+            "[tags]\ncase = \"first-seen\"\n",
+        )
+        .unwrap();
+
+        replace_everywhere(
+            csv.to_str().unwrap(),
+            &[String::from("rust")],
+            "Rust",
+            false,
+            false,
+            "Rename tag rust to Rust",
+        )
+        .unwrap();
+
+        let tags = read_lines(csv.to_str().unwrap(), ParseMode::Strict)
+            .unwrap()
+            .into_iter()
+            .map(|line| line.tags)
+            .collect::<Vec<Vec<String>>>();
+        assert_eq!(
+            tags,
+            vec![
+                vec![String::from("Rust"), String::from("web")],
+                vec![String::from("Rust")]
+            ]
+        );
+    }
+
+    #[test]
+    fn count_bookmarks_per_tag() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        fs::write(
+            &csv,
+            "URL|DESCRIPTION|TAGS\nhttps://a.com|A|Rust,rust,go\nhttps://b.com|B|RUST\nhttps://c.com|C|\n",
+        )
+        .unwrap();
+
+        let map = get_tags(csv.to_str().unwrap(), ParseMode::Strict).unwrap();
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(
+            map["rust"],
+            TagUsage {
+                tags: vec![
+                    String::from("Rust"),
+                    String::from("rust"),
+                    String::from("RUST")
+                ],
+                count: 2,
+            }
+        );
+        assert_eq!(map["go"].count, 1);
+    }
+}
@@ -1,11 +1,16 @@
-use clap::Parser;
-use validator::{Validate, ValidationError};
+use clap::{Parser, ValueEnum};
+use validator::Validate;
 
 #[derive(Debug, Parser)]
 #[clap(name = "bm", about = "Bookmark Manager CLI")]
 pub struct Opts {
     #[clap(subcommand)]
     pub cmd: Command,
+
+    /// Fail on a malformed line in the bookmarks file. Otherwise, commands that only read the file skip it with a
+    /// warning (commands that change the file always fail so no lines are lost)
+    #[clap(long, global = true)]
+    pub strict: bool,
 }
 
 // subcommands: https://github.com/TeXitoi/structopt/blob/master/examples/enum_tuple.rs
@@ -16,33 +21,236 @@ pub enum Command {
     #[clap(name = "add", alias = "a")]
     Add(Add),
 
+    /// Change the URL, description or tags of a bookmark
+    #[clap(name = "edit", alias = "e")]
+    Edit(Edit),
+
+    /// Remove bookmarks by exact URL or by the same REGEX/tag filters as search
+    #[clap(name = "rm", alias = "remove")]
+    Remove(Remove),
+
+    /// Export bookmarks so they can be imported into a browser
+    #[clap(name = "export")]
+    Export(Export),
+
+    /// Import bookmarks from a browser
+    #[clap(name = "import")]
+    Import(Import),
+
+    /// Open matched bookmarks in the browser
+    #[clap(name = "open", alias = "o")]
+    Open(Open),
+
     /// Search for a bookmark
     #[clap(name = "search", alias = "s")]
     Search(Search),
 
+    /// Upgrade the bookmarks file to include every known column (ADDED, NOTES, ARCHIVED). ADDED is filled from the git history
+    #[clap(name = "migrate")]
+    Migrate(Migrate),
+
     /// List all tags
     #[clap(name = "tags", alias = "t")]
     Tags(Tags),
+
+    /// Find bookmarks with the same canonical URL and merge them
+    #[clap(name = "dedupe")]
+    Dedupe(Dedupe),
+
+    /// Check every line of the bookmarks file for problems. Exits with an error if there are any
+    #[clap(name = "check", alias = "fsck")]
+    Check(Check),
+
+    /// Request every bookmarked URL and report which are broken or redirect
+    #[clap(name = "linkcheck")]
+    Linkcheck(Linkcheck),
+}
+
+/// Tag filters shared by the commands that find bookmarks the same way as search
+#[derive(Debug, Parser)]
+pub struct TagFilters {
+    /// Only match bookmarks with the given tags (can be none)
+    #[clap(short, long = "tag")]
+    pub tags: Vec<String>,
+
+    /// Boolean tag expression the bookmarks must match, e.g. "(rust | go) & !internal". See the README for the syntax
+    #[clap(short = 'w', long = "where", value_name = "EXPR")]
+    pub where_expr: Option<String>,
+}
+
+/// Shared by the commands that change the bookmarks file
+#[derive(Debug, Parser)]
+pub struct GitOpts {
+    /// Turn off automatically committing bookmarks file if it is in a git repo
+    #[clap(long = "no-commit", action = clap::ArgAction::SetFalse)]
+    pub commit: bool,
 }
 
 #[derive(Debug, Parser, Validate)]
 pub struct Add {
     /// URL to bookmark
-    #[validate(url, custom(function = "validate_no_pipe"))]
+    #[validate(url)]
     pub url: String,
 
-    /// Description of the URL
-    #[validate(custom(function = "validate_no_pipe"))]
-    pub description: String,
+    /// Description of the URL. Defaults to the title of the page, or its host if the page can't be fetched
+    pub description: Option<String>,
+
+    /// Use the title of the page even if there is a DESCRIPTION, which is then only used if the title can't be fetched
+    #[clap(long)]
+    pub fetch_title: bool,
 
     /// Tags to group bookmarks
     #[clap(short, long = "tag")]
-    #[validate(custom(function = "validate_tags"))]
     pub tags: Vec<String>,
 
-    /// Turn off automatically committing bookmarks file if it is in a git repo
-    #[clap(long = "no-commit", action = clap::ArgAction::SetFalse)]
-    pub commit: bool,
+    /// Add the tags suggested from bookmarks on the same domain or with similar descriptions
+    #[clap(long)]
+    pub auto_tag: bool,
+
+    /// Store the canonical form of the URL, e.g. without tracking parameters. See the README for what changes
+    #[clap(long)]
+    pub canonical: bool,
+
+    #[clap(flatten)]
+    pub git: GitOpts,
+}
+
+#[derive(Debug, Parser, Validate)]
+pub struct Edit {
+    /// URL of the bookmark to edit
+    pub url: String,
+
+    /// Replace the URL of the bookmark
+    #[clap(long = "url", value_name = "NEW_URL")]
+    #[validate(url)]
+    pub new_url: Option<String>,
+
+    /// Replace the description of the bookmark
+    #[clap(short, long)]
+    pub description: Option<String>,
+
+    /// Tags to add to the bookmark
+    #[clap(short, long = "tag")]
+    pub tags: Vec<String>,
+
+    /// Tags to remove from the bookmark (case insensitive)
+    #[clap(short, long = "remove-tag")]
+    pub remove_tags: Vec<String>,
+
+    #[clap(flatten)]
+    pub git: GitOpts,
+}
+
+#[cfg(test)]
+impl Edit {
+    /// An edit of the bookmark which doesn't change anything
+    pub fn new(url: &str) -> Self {
+        Edit {
+            url: String::from(url),
+            new_url: None,
+            description: None,
+            tags: Vec::new(),
+            remove_tags: Vec::new(),
+            git: GitOpts { commit: true },
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Remove {
+    /// Exact URL of the bookmark to remove.  Otherwise, a Perl style REGEX to run against bookmark URL and description
+    /// (use url:REGEX to match URLs with a REGEX).  Omit to remove by tags only.
+    pub regex: Option<String>,
+
+    #[clap(flatten)]
+    pub filters: TagFilters,
+
+    /// Remove the matched bookmarks without asking for confirmation
+    #[clap(short, long)]
+    pub yes: bool,
+
+    #[clap(flatten)]
+    pub git: GitOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct Export {
+    #[clap(subcommand)]
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Parser)]
+pub enum ExportFormat {
+    /// Netscape bookmark HTML file which can be imported by Chrome, Firefox, Edge, etc.
+    #[clap(name = "html")]
+    Html(ExportHtml),
+}
+
+#[derive(Debug, Parser)]
+pub struct ExportHtml {
+    /// Perl style REGEX to run against bookmark URL and description.  Omit to export everything (or by tags only).
+    pub regex: Option<String>,
+
+    #[clap(flatten)]
+    pub filters: TagFilters,
+
+    /// How tags are turned into browser folders
+    #[clap(long, value_enum, default_value_t = FolderMode::FirstTag)]
+    pub folders: FolderMode,
+
+    /// File to write to instead of stdout
+    #[clap(short, long)]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FolderMode {
+    /// Each bookmark goes in a folder named after its first tag
+    FirstTag,
+    /// Each bookmark goes in a folder for every one of its tags, so it can show up more than once
+    PerTag,
+    /// No folders. Tags are kept in the TAGS attribute which Firefox supports
+    Flat,
+}
+
+#[derive(Debug, Parser)]
+pub struct Import {
+    #[clap(subcommand)]
+    pub format: ImportFormat,
+}
+
+#[derive(Debug, Parser)]
+pub enum ImportFormat {
+    /// Netscape bookmark HTML file as exported by Chrome, Firefox, Edge, etc.  Folders are turned into tags
+    #[clap(name = "html")]
+    Html(ImportHtml),
+}
+
+#[derive(Debug, Parser)]
+pub struct ImportHtml {
+    /// Exported bookmarks file
+    pub file: String,
+
+    #[clap(flatten)]
+    pub git: GitOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct Open {
+    /// Perl style REGEX to run against bookmark URL and description.  Omit to do tags only search.
+    pub regex: Option<String>,
+
+    #[clap(flatten)]
+    pub filters: TagFilters,
+
+    /// Open every matched bookmark instead of asking which one when more than one matches
+    #[clap(short, long)]
+    pub all: bool,
+
+    /// Command to open the URL with (the URL is appended as the last argument).
+    /// Defaults to $BOOKMARK_MANAGER_OPENER, then $BROWSER, then the platform's default (e.g. xdg-open)
+    #[clap(long)]
+    pub opener: Option<String>,
 }
 
 #[derive(Debug, Parser)]
@@ -50,9 +258,129 @@ pub struct Search {
     /// Perl style REGEX to run against bookmark URL and description.  Omit to do tags only search.
     pub regex: Option<String>,
 
-    /// Only apply REGEX to bookmarks with the given tags (can be none)
-    #[clap(short, long = "tag")]
-    pub tags: Vec<String>,
+    #[clap(flatten)]
+    pub filters: TagFilters,
+
+    /// Treat REGEX as plain text and rank bookmarks by how similar they are to it, allowing for typos
+    #[clap(long)]
+    pub fuzzy: bool,
+
+    /// Order of the matched bookmarks. Defaults to file order (or relevance with --fuzzy)
+    #[clap(long, value_enum)]
+    pub sort: Option<SortKey>,
+
+    /// Reverse the order of the matched bookmarks
+    #[clap(long)]
+    pub reverse: bool,
+
+    /// Skip the first N matched bookmarks (after sorting). Use with --limit to page through the results
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    pub offset: usize,
+
+    /// Only show the first N matched bookmarks
+    #[clap(long, value_name = "N")]
+    pub limit: Option<usize>,
+
+    /// How to output the matched bookmarks. See the README for the machine-readable formats
+    #[clap(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+
+    /// Include the character ranges matched by REGEX in json and jsonl output (not allowed with other formats)
+    #[clap(long)]
+    pub spans: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// Alphabetical by URL
+    Url,
+    /// Alphabetical by description
+    Description,
+    /// Alphabetical by the URL's host, then by URL
+    Domain,
+    /// Newest first. Bookmarks without an added date are last
+    Added,
+    /// Best match first
+    Relevance,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns with the matched text highlighted
+    Table,
+    /// A JSON array of bookmarks
+    Json,
+    /// A JSON bookmark per line
+    Jsonl,
+    /// Tab separated URL, description and comma separated tags
+    Tsv,
+    /// The same pipe separated format as the bookmarks file (without the header)
+    Csv,
+}
+
+#[derive(Debug, Parser)]
+pub struct Migrate {
+    #[clap(flatten)]
+    pub git: GitOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct Check {
+    /// Fix the problems that don't need a person to decide what was meant, e.g. trailing white space
+    #[clap(long)]
+    pub fix: bool,
+
+    #[clap(flatten)]
+    pub git: GitOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct Linkcheck {
+    /// Perl style REGEX to run against bookmark URL and description. Omit to check every bookmark.
+    pub regex: Option<String>,
+
+    #[clap(flatten)]
+    pub filters: TagFilters,
+
+    /// Number of links to check at the same time
+    #[clap(short, long, default_value_t = 8)]
+    pub jobs: usize,
+
+    /// Seconds to wait for each request
+    #[clap(long, value_name = "SECONDS", default_value_t = 10)]
+    pub timeout: u64,
+
+    /// Add the "dead" tag to bookmarks whose link is broken
+    #[clap(long)]
+    pub tag_dead: bool,
+
+    /// Replace URLs that permanently redirect (301 or 308) with where they end up
+    #[clap(long)]
+    pub update_redirects: bool,
+
+    #[clap(flatten)]
+    pub git: GitOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct Dedupe {
+    /// Merge every group of duplicates without asking
+    #[clap(long)]
+    pub auto: bool,
+
+    /// Which description the merged bookmark keeps, unless one is picked when asked
+    #[clap(long, value_enum, default_value_t = KeepDescription::Longest)]
+    pub description: KeepDescription,
+
+    #[clap(flatten)]
+    pub git: GitOpts,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeepDescription {
+    Longest,
+    /// The description of the bookmark that is first in the file
+    First,
 }
 
 #[derive(Debug, Parser)]
@@ -61,96 +389,218 @@ pub struct Tags {
     /// Defaults to false which outputs in a more human-readable way. i.e. Different capitalization of the same tag is output comma separated on the same line
     #[clap(long = "machine", action = clap::ArgAction::SetTrue)]
     pub machine: bool,
+
+    /// Show how many bookmarks have each tag
+    #[clap(short, long)]
+    pub count: bool,
+
+    /// Order of the tags. Count is the most used first
+    #[clap(long, value_enum, default_value_t = TagSort::Name)]
+    pub sort: TagSort,
+
+    /// Only show tags used by fewer than N bookmarks (i.e. candidates to merge or drop)
+    #[clap(long, value_name = "N")]
+    pub unused_below: Option<usize>,
+
+    /// Output the tags and their counts as JSON
+    #[clap(long)]
+    pub json: bool,
+
+    /// Show tags containing / as an indented hierarchy (e.g. lang/rust/async) with the number of bookmarks at or below
+    /// each level
+    #[clap(long, conflicts_with_all = ["json", "machine", "unused_below"])]
+    pub tree: bool,
+
+    #[clap(subcommand)]
+    pub action: Option<TagsAction>,
 }
 
-fn validate_tags(values: &[String]) -> std::result::Result<(), ValidationError> {
-    for val in values {
-        validate_no_pipe(val)?;
-        validate_no_comma(val)?;
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TagSort {
+    Name,
+    Count,
+}
 
-    Ok(())
+#[derive(Debug, Parser)]
+pub enum TagsAction {
+    /// Rename a tag on every bookmark
+    #[clap(name = "rename")]
+    Rename(TagsRename),
+
+    /// Replace several tags with one tag on every bookmark
+    #[clap(name = "merge")]
+    Merge(TagsMerge),
+
+    /// Apply the tag policy from the settings file to every bookmark
+    #[clap(name = "normalize")]
+    Normalize(TagsNormalize),
 }
 
-fn validate_no_comma(val: &str) -> std::result::Result<(), ValidationError> {
-    if val.contains(',') {
-        return Err(ValidationError::new("contains_comma"));
-    }
+#[derive(Debug, Parser)]
+pub struct TagsRename {
+    /// Tag to rename
+    pub old: String,
+
+    /// New name of the tag
+    pub new: String,
 
-    Ok(())
+    /// Also rename tags which only differ from OLD by case. e.g. fix the case of every variant with `rename rust Rust -i`
+    #[clap(short, long)]
+    pub ignore_case: bool,
+
+    #[clap(flatten)]
+    pub git: GitOpts,
 }
 
-fn validate_no_pipe(val: &str) -> std::result::Result<(), ValidationError> {
-    if val.contains('|') {
-        return Err(ValidationError::new("contains_pipe"));
-    }
+#[derive(Debug, Parser)]
+pub struct TagsNormalize {
+    #[clap(flatten)]
+    pub git: GitOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct TagsMerge {
+    /// Tags to merge
+    #[clap(required = true)]
+    pub tags: Vec<String>,
+
+    /// Tag which replaces the merged tags (it can be one of them)
+    #[clap(long)]
+    pub into: String,
 
-    Ok(())
+    /// Also merge tags which only differ from TAGS by case
+    #[clap(short, long)]
+    pub ignore_case: bool,
+
+    #[clap(flatten)]
+    pub git: GitOpts,
 }
 
 #[cfg(test)]
 mod add_tests {
     use validator::Validate;
 
-    use crate::config::Add;
+    use crate::config::{Add, GitOpts};
 
     #[test]
     fn invalid_url() {
         let add_opts = Add {
             url: String::from("not_a_url"),
-            description: String::from("description"),
+            description: Some(String::from("description")),
+            fetch_title: false,
             tags: Vec::new(),
-            commit: true,
+            auto_tag: false,
+            canonical: false,
+            git: GitOpts { commit: true },
         };
 
         assert!(add_opts.validate().is_err());
     }
 
     #[test]
-    fn pipe_in_url() {
+    fn pipe_in_url_host() {
         let add_opts = Add {
             url: String::from("https://wwww.go|ogle.com"),
-            description: String::from("description"),
+            description: Some(String::from("description")),
+            fetch_title: false,
             tags: Vec::new(),
-            commit: true,
+            auto_tag: false,
+            canonical: false,
+            git: GitOpts { commit: true },
         };
 
         assert!(add_opts.validate().is_err());
     }
 
+    #[test]
+    fn pipe_in_url_query() {
+        let add_opts = Add {
+            url: String::from("https://wwww.google.com/search?q=a|b"),
+            description: Some(String::from("description")),
+            fetch_title: false,
+            tags: Vec::new(),
+            auto_tag: false,
+            canonical: false,
+            git: GitOpts { commit: true },
+        };
+
+        assert!(add_opts.validate().is_ok());
+    }
+
     #[test]
     fn pipe_in_description() {
         let add_opts = Add {
             url: String::from("https://wwww.google.com"),
-            description: String::from("descr|iption"),
+            description: Some(String::from("descr|iption")),
+            fetch_title: false,
             tags: Vec::new(),
-            commit: true,
+            auto_tag: false,
+            canonical: false,
+            git: GitOpts { commit: true },
         };
 
-        assert!(add_opts.validate().is_err());
+        assert!(add_opts.validate().is_ok());
     }
 
     #[test]
     fn pipe_in_tags() {
         let add_opts = Add {
             url: String::from("https://wwww.google.com"),
-            description: String::from("description"),
+            description: Some(String::from("description")),
+            fetch_title: false,
             tags: vec![String::from("t|ag")],
-            commit: true,
+            auto_tag: false,
+            canonical: false,
+            git: GitOpts { commit: true },
         };
 
-        assert!(add_opts.validate().is_err());
+        assert!(add_opts.validate().is_ok());
     }
 
     #[test]
     fn comma_in_tags() {
         let add_opts = Add {
             url: String::from("https://wwww.google.com"),
-            description: String::from("description"),
+            description: Some(String::from("description")),
+            fetch_title: false,
             tags: vec![String::from("t,ag")],
-            commit: true,
+            auto_tag: false,
+            canonical: false,
+            git: GitOpts { commit: true },
         };
 
-        assert!(add_opts.validate().is_err());
+        assert!(add_opts.validate().is_ok());
+    }
+}
+
+#[cfg(test)]
+mod edit_tests {
+    use validator::Validate;
+
+    use crate::config::Edit;
+
+    #[test]
+    fn invalid_new_url() {
+        let edit_opts = Edit {
+            new_url: Some(String::from("not_a_url")),
+            ..Edit::new("https://wwww.google.com")
+        };
+
+        assert!(edit_opts.validate().is_err());
+    }
+
+    #[test]
+    fn pipe_in_description() {
+        let edit_opts = Edit {
+            description: Some(String::from("descr|iption")),
+            ..Edit::new("https://wwww.google.com")
+        };
+
+        assert!(edit_opts.validate().is_ok());
+    }
+
+    #[test]
+    fn nothing_to_validate() {
+        assert!(Edit::new("https://wwww.google.com").validate().is_ok());
     }
 }
@@ -0,0 +1,41 @@
+use anyhow::Result;
+
+use crate::cli_output::utils::print_success;
+use crate::csv::{CsvLock, LockMode};
+use crate::git::Git;
+
+/// A change to the CSV, which is locked until the change is finished
+pub struct CsvChange {
+    /// None if the user doesn't want to commit changes or the CSV isn't in a git repo
+    git: Option<Git>,
+    _lock: CsvLock,
+}
+
+impl CsvChange {
+    /// Lock the CSV and open the git repo unless the user doesn't want to commit changes.
+    /// Fails if the repo has uncommitted changes since it wouldn't be safe to commit on top of them.
+    pub fn begin(csv: &str, commit: bool) -> Result<Self> {
+        let lock = CsvLock::acquire(csv, LockMode::Exclusive)?;
+        let git = Git::open_clean(commit, csv)?;
+
+        Ok(Self { git, _lock: lock })
+    }
+
+    /// The git repo changes are committed to, if any
+    pub fn git(&self) -> Option<&Git> {
+        self.git.as_ref()
+    }
+
+    /// Commit the changed CSV (if there is a git repo) and report the change with the summary
+    pub fn finish(self, commit_msg: &str, summary: &str) -> Result<()> {
+        match &self.git {
+            Some(git) => {
+                git.add_and_commit(commit_msg)?;
+                print_success(format!("{summary} and committed to git").as_str());
+            }
+            None => print_success(summary),
+        }
+
+        Ok(())
+    }
+}
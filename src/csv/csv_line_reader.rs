@@ -1,11 +1,27 @@
-use anyhow::{Context, Result, ensure};
+use anyhow::{Context, Result, bail, ensure};
 use std::fs::File;
 use std::io::{BufRead, BufReader, Lines};
 
 use super::Line;
+use super::escape::{COLUMN_SEPARATOR, TAG_SEPARATOR, split_escaped, unescape};
+use super::schema::{Column, Schema};
+use crate::cli_output::utils::print_stderr_warning;
+
+/// What to do with a line that can't be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Return an error
+    Strict,
+    /// Skip it with a warning
+    Lenient,
+}
 
 pub struct CsvLineReader {
+    schema: Schema,
     lines: Lines<BufReader<File>>,
+    mode: ParseMode,
+    /// Line number of the last line read (the header is line 1)
+    line_number: usize,
 }
 
 impl CsvLineReader {
@@ -14,10 +30,32 @@ impl CsvLineReader {
         let reader = BufReader::new(file);
         let mut iter = reader.lines();
 
-        // Skip headers (i.e. first line)
-        iter.next();
+        // The headers (i.e. first line) determine which columns each line has
+        let schema = match iter.next() {
+            Some(header) => {
+                Schema::parse_header(header.context("Could not read CSV header")?.as_str())?
+            }
+            None => Schema::required(),
+        };
+
+        Ok(Self {
+            schema,
+            lines: iter,
+            mode: ParseMode::Strict,
+            line_number: 1,
+        })
+    }
+
+    /// Lines are strictly parsed unless changed. Only read-only commands should skip lines since anything that
+    /// rewrites the file would lose them.
+    pub fn with_mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
 
-        Ok(Self { lines: iter })
+    /// The columns of the CSV as given by its header
+    pub fn schema(&self) -> &Schema {
+        &self.schema
     }
 }
 
@@ -26,46 +64,107 @@ impl Iterator for CsvLineReader {
     type Item = Result<Line>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let line_result = self.lines.next()?;
+        loop {
+            let line_result = self.lines.next()?;
+            self.line_number += 1;
 
-        let line = match line_result.context("Could not read line from CSV") {
-            Ok(line) => line,
-            Err(e) => return Some(Err(e)),
-        };
-        let res: Result<Line> = parse_line(line.as_str());
+            let line = match line_result.context("Could not read line from CSV") {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
 
-        Some(res)
+            match (parse_line(&self.schema, line.as_str()), self.mode) {
+                (Ok(parsed), _) => return Some(Ok(parsed)),
+                (Err(e), ParseMode::Strict) => {
+                    return Some(Err(e.context(format!(
+                        "Line {} of the CSV is invalid",
+                        self.line_number
+                    ))));
+                }
+                (Err(e), ParseMode::Lenient) => {
+                    print_stderr_warning(
+                        format!("Skipping line {} of the CSV: {e}", self.line_number).as_str(),
+                    );
+                }
+            }
+        }
     }
 }
 
-fn parse_line(line: &str) -> Result<Line> {
-    let line_parts = line.split('|').collect::<Vec<&str>>();
+/// Parse a line of the CSV (not the header) with the columns in the schema
+pub fn parse_line(schema: &Schema, line: &str) -> Result<Line> {
+    let line_parts = split(schema, line, COLUMN_SEPARATOR);
     ensure!(
-        line_parts.len() == 3,
-        format!("CSV line has more than 3 columns: {line}")
+        line_parts.len() == schema.columns().len(),
+        format!(
+            "CSV line has {} columns instead of {}: {line}",
+            line_parts.len(),
+            schema.columns().len()
+        )
     );
 
-    let url = String::from(line_parts[0]);
-    let description = String::from(line_parts[1]);
-    let tags = line_parts[2]
-        .split(',')
-        .map(String::from)
-        .collect::<Vec<String>>();
-
-    Ok(Line {
-        url,
-        description,
-        tags,
-    })
+    let mut parsed = Line::default();
+    for (column, value) in schema.columns().iter().zip(line_parts) {
+        match column {
+            Column::Url => parsed.url = read_value(schema, value),
+            Column::Description => parsed.description = read_value(schema, value),
+            Column::Tags => {
+                parsed.tags = match value {
+                    "" => Vec::new(),
+                    tags => split(schema, tags, TAG_SEPARATOR)
+                        .into_iter()
+                        .map(|tag| read_value(schema, tag))
+                        .collect::<Vec<String>>(),
+                }
+            }
+            Column::Added => parsed.added = optional(schema, value),
+            Column::Notes => parsed.notes = optional(schema, value),
+            Column::Archived => {
+                parsed.archived = match value {
+                    "" | "false" => false,
+                    "true" => true,
+                    _ => bail!("Invalid {} value {value}: {line}", column.header()),
+                }
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Values in files that aren't escaped are read exactly as they are
+fn split<'a>(schema: &Schema, text: &'a str, separator: char) -> Vec<&'a str> {
+    match schema.is_escaped() {
+        true => split_escaped(text, separator),
+        false => text.split(separator).collect(),
+    }
+}
+
+fn read_value(schema: &Schema, value: &str) -> String {
+    match schema.is_escaped() {
+        true => unescape(value),
+        false => value.to_string(),
+    }
+}
+
+/// Empty optional columns are None
+fn optional(schema: &Schema, value: &str) -> Option<String> {
+    match value {
+        "" => None,
+        value => Some(read_value(schema, value)),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::csv::csv_line_reader::parse_line;
+    use crate::csv::csv_line_reader::{CsvLineReader, ParseMode, parse_line};
+    use crate::csv::schema::Schema;
+    use std::fs;
+    use tempfile::tempdir;
 
     #[test]
     fn invalid_line() {
-        assert!(parse_line("four|pipes|in|line").is_err());
+        assert!(parse_line(&Schema::required(), "four|pipes|in|line").is_err());
     }
 
     #[test]
@@ -75,10 +174,98 @@ mod tests {
         let tags = vec!["Search", "Engine"];
         let line_text = format!("{}|{}|{}", &url, &description, &tags.join(","));
 
-        let line = parse_line(line_text.as_str()).unwrap();
+        let line = parse_line(&Schema::required(), line_text.as_str()).unwrap();
 
         assert_eq!(line.url, url);
         assert_eq!(line.description, description);
         assert_eq!(line.tags, tags);
     }
+
+    #[test]
+    fn escaped_line() {
+        let line = parse_line(
+            &Schema::required(),
+            "https://a.com/?q=a\\|b|Pipe \\| and \\\\|a\\,b,c",
+        )
+        .unwrap();
+
+        assert_eq!(line.url, "https://a.com/?q=a|b");
+        assert_eq!(line.description, "Pipe | and \\");
+        assert_eq!(line.tags, vec!["a,b", "c"]);
+    }
+
+    #[test]
+    fn legacy_file_is_literal() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        fs::write(
+            &csv,
+            "URL|DESCRIPTION|TAGS\nhttps://a.com|Windows dir C:\\|win\nhttps://b.com|regex \\n newline \\\\|a\\,b\n",
+        )
+        .unwrap();
+
+        let reader = CsvLineReader::new(csv.to_str().unwrap()).unwrap();
+        assert!(!reader.schema().is_escaped());
+        let lines = reader.collect::<anyhow::Result<Vec<_>>>().unwrap();
+
+        assert_eq!(lines[0].description, "Windows dir C:\\");
+        assert_eq!(lines[0].tags, vec!["win"]);
+        assert_eq!(lines[1].description, "regex \\n newline \\\\");
+        assert_eq!(lines[1].tags, vec!["a\\", "b"]);
+    }
+
+    #[test]
+    fn no_tags() {
+        let line = parse_line(&Schema::required(), "https://google.com|Google|").unwrap();
+
+        assert!(line.tags.is_empty());
+    }
+
+    #[test]
+    fn extra_columns() {
+        let schema = Schema::parse_header("ADDED|URL|DESCRIPTION|TAGS|NOTES|ARCHIVED").unwrap();
+        let line = parse_line(
+            &schema,
+            "2021-07-22T10:00:00Z|https://google.com|Google|Search||true",
+        )
+        .unwrap();
+
+        assert_eq!(line.url, "https://google.com");
+        assert_eq!(line.added.as_deref(), Some("2021-07-22T10:00:00Z"));
+        assert_eq!(line.notes, None);
+        assert!(line.archived);
+    }
+
+    #[test]
+    fn parse_modes() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        fs::write(
+            &csv,
+            "URL|DESCRIPTION|TAGS\nhttps://a.com|A|\nhttps://b.com|B|c|d\nhttps://c.com|C|\n",
+        )
+        .unwrap();
+        let csv = csv.to_str().unwrap();
+
+        let err = CsvLineReader::new(csv)
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "Line 3 of the CSV is invalid");
+
+        let urls = CsvLineReader::new(csv)
+            .unwrap()
+            .with_mode(ParseMode::Lenient)
+            .map(|line| line.unwrap().url)
+            .collect::<Vec<String>>();
+        assert_eq!(urls, vec!["https://a.com", "https://c.com"]);
+    }
+
+    #[test]
+    fn invalid_archived() {
+        let schema = Schema::parse_header("URL|DESCRIPTION|TAGS|ARCHIVED").unwrap();
+
+        assert!(parse_line(&schema, "https://google.com|Google||maybe").is_err());
+    }
 }
@@ -2,23 +2,159 @@ use anyhow::{Context, Result};
 use std::fs::{File, OpenOptions};
 use std::io::Write;
 
+use super::escape::{
+    COLUMN_SEPARATOR, TAG_SEPARATOR, escape_column, escape_tag, is_literal_column, is_literal_tag,
+};
+use super::schema::{Column, Schema};
+use super::{CsvLineReader, Line, rewrite_csv};
+
 pub struct CsvLineWriter {
+    csv: String,
+    schema: Schema,
     file: File,
 }
 
 impl CsvLineWriter {
     pub fn new(csv: &str) -> Result<Self> {
-        let file = OpenOptions::new()
-            .append(true)
-            .open(csv)
-            .context("Could not open CSV for writing")?;
+        // Lines need to be written with the same columns as the existing ones
+        let schema = Schema::read(csv)?;
+        let file = open_for_append(csv)?;
 
-        Ok(Self { file })
+        Ok(Self {
+            csv: String::from(csv),
+            schema,
+            file,
+        })
     }
 
-    pub fn write_line(&mut self, url: &str, description: &str, tags: &[String]) -> Result<()> {
-        writeln!(self.file, "{}|{}|{}", url, description, tags.join(","))
+    pub fn write_line(&mut self, line: &Line) -> Result<()> {
+        // A file that isn't escaped can't store the value, so it has to be rewritten with escaping first
+        if !self.schema.is_escaped() && !is_literal_line(line) {
+            let lines = CsvLineReader::new(&self.csv)?.collect::<Result<Vec<Line>>>()?;
+            self.schema = self.schema.clone().with_escaping(true);
+            rewrite_csv(&self.csv, &self.schema, &lines)?;
+            // The rewrite replaced the file
+            self.file = open_for_append(&self.csv)?;
+        }
+
+        writeln!(self.file, "{}", format_line(&self.schema, line))
             .context("Could not add bookmark")?;
         Ok(())
     }
 }
+
+fn open_for_append(csv: &str) -> Result<File> {
+    OpenOptions::new()
+        .append(true)
+        .open(csv)
+        .context("Could not open CSV for writing")
+}
+
+/// Whether the bookmark can be written to a file that isn't escaped
+fn is_literal_line(line: &Line) -> bool {
+    [
+        Some(line.url.as_str()),
+        Some(line.description.as_str()),
+        line.added.as_deref(),
+        line.notes.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .all(is_literal_column)
+        && line.tags.iter().all(|tag| is_literal_tag(tag))
+}
+
+/// Format a bookmark as a single line of the CSV (without the new line).
+/// Only the columns in the schema are written. Values are only escaped if the schema is, so the caller has to make
+/// sure the values can be written literally otherwise.
+pub fn format_line(schema: &Schema, line: &Line) -> String {
+    let escape_column = |value: &str| match schema.is_escaped() {
+        true => escape_column(value),
+        false => value.to_string(),
+    };
+    let escape_tag = |value: &str| match schema.is_escaped() {
+        true => escape_tag(value),
+        false => value.to_string(),
+    };
+
+    schema
+        .columns()
+        .iter()
+        .map(|column| match column {
+            Column::Url => escape_column(&line.url),
+            Column::Description => escape_column(&line.description),
+            Column::Tags => line
+                .tags
+                .iter()
+                .map(|tag| escape_tag(tag))
+                .collect::<Vec<String>>()
+                .join(TAG_SEPARATOR.to_string().as_str()),
+            Column::Added => line.added.as_deref().map(escape_column).unwrap_or_default(),
+            Column::Notes => line.notes.as_deref().map(escape_column).unwrap_or_default(),
+            Column::Archived => line.archived.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(COLUMN_SEPARATOR.to_string().as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv::Line;
+    use crate::csv::csv_line_writer::{CsvLineWriter, format_line};
+    use crate::csv::schema::Schema;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn escape_separators() {
+        let line = format_line(
+            &Schema::required(),
+            &Line::new("https://a.com/?q=a|b", "Pipe | and \\", &["a,b", "c"]),
+        );
+
+        assert_eq!(line, "https://a.com/?q=a\\|b|Pipe \\| and \\\\|a\\,b,c");
+    }
+
+    #[test]
+    fn extra_columns() {
+        let line = Line {
+            added: Some(String::from("2021-07-22T10:00:00Z")),
+            ..Line::new("https://google.com", "Google", &["Search"])
+        };
+
+        assert_eq!(
+            format_line(&Schema::latest(), &line),
+            "https://google.com|Google|Search|2021-07-22T10:00:00Z||false"
+        );
+        // Columns not in the schema are dropped
+        assert_eq!(
+            format_line(&Schema::required(), &line),
+            "https://google.com|Google|Search"
+        );
+    }
+
+    #[test]
+    fn legacy_file_is_upgraded_when_needed() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        fs::write(&csv, "URL|DESCRIPTION|TAGS\nhttps://a.com|C:\\|win\n").unwrap();
+        let mut writer = CsvLineWriter::new(csv.to_str().unwrap()).unwrap();
+
+        // Written as is since it doesn't need escaping
+        writer
+            .write_line(&Line::new("https://b.com", "B\\n", &[]))
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&csv).unwrap(),
+            "URL|DESCRIPTION|TAGS\nhttps://a.com|C:\\|win\nhttps://b.com|B\\n|\n"
+        );
+
+        writer
+            .write_line(&Line::new("https://c.com", "C|D", &[]))
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&csv).unwrap(),
+            "URL|DESCRIPTION|TAGS|#escaped\nhttps://a.com|C:\\\\|win\nhttps://b.com|B\\\\n|\nhttps://c.com|C\\|D|\n"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Backslash escaping so that the column (`|`) and tag (`,`) separators can be used inside values.
+//!
+//! Only `\\`, `\|`, `\,`, `\n` and `\r` are escape sequences.  Any other backslash is kept as is.
+//!
+//! Escaping only applies to files whose header ends with [`ESCAPED_MARKER`]. Files written before escaping existed
+//! don't have it, so their values are read literally (e.g. a description ending with `C:\` or containing `\n`).
+//! They are upgraded the first time they are rewritten, or when a value that can't be stored literally is added.
+
+pub const COLUMN_SEPARATOR: char = '|';
+pub const TAG_SEPARATOR: char = ',';
+
+/// Last cell of the header row of a file with escaped values. It isn't a column, so rows don't have a value for it.
+pub const ESCAPED_MARKER: &str = "#escaped";
+
+/// Escape a URL or description
+pub fn escape_column(value: &str) -> String {
+    escape(value, &[COLUMN_SEPARATOR])
+}
+
+/// Escape a single tag. Tags also need the tag separator escaped since they share a column.
+pub fn escape_tag(value: &str) -> String {
+    escape(value, &[COLUMN_SEPARATOR, TAG_SEPARATOR])
+}
+
+fn escape(value: &str, separators: &[char]) -> String {
+    let mut out = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c if separators.contains(&c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Whether a URL or description can be written to a file that isn't escaped
+pub fn is_literal_column(value: &str) -> bool {
+    !value.contains([COLUMN_SEPARATOR, '\n', '\r'])
+}
+
+/// Whether a tag can be written to a file that isn't escaped
+pub fn is_literal_tag(value: &str) -> bool {
+    is_literal_column(value) && !value.contains(TAG_SEPARATOR)
+}
+
+/// Split on every separator that isn't escaped. The parts are still escaped.
+pub fn split_escaped(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == separator {
+            parts.push(&text[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&text[start..]);
+
+    parts
+}
+
+/// Reverse of escaping. Unknown escape sequences are left untouched.
+pub fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.peek() {
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(&c) if c == COLUMN_SEPARATOR || c == TAG_SEPARATOR => out.push(c),
+            // Not an escape sequence so keep the backslash
+            _ => {
+                out.push('\\');
+                continue;
+            }
+        }
+        chars.next();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv::escape::{escape_column, escape_tag, split_escaped, unescape};
+
+    #[test]
+    fn escape_separators() {
+        assert_eq!(
+            escape_column("https://a.com/?q=a|b,c"),
+            "https://a.com/?q=a\\|b,c"
+        );
+        assert_eq!(escape_tag("a|b,c"), "a\\|b\\,c");
+        assert_eq!(escape_column("one\ntwo\\"), "one\\ntwo\\\\");
+    }
+
+    #[test]
+    fn split_on_unescaped_separator_only() {
+        assert_eq!(split_escaped("a\\|b|c||", '|'), vec!["a\\|b", "c", "", ""]);
+        assert_eq!(split_escaped("a\\\\|b", '|'), vec!["a\\\\", "b"]);
+    }
+
+    #[test]
+    fn unescape_round_trip() {
+        let value = "a|b,c\\d\ne";
+        assert_eq!(unescape(escape_column(value).as_str()), value);
+        assert_eq!(unescape(escape_tag(value).as_str()), value);
+    }
+
+    #[test]
+    fn unknown_escape_is_kept() {
+        assert_eq!(unescape("C:\\Users\\me"), "C:\\Users\\me");
+        assert_eq!(unescape("trailing\\"), "trailing\\");
+    }
+}
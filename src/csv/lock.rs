@@ -0,0 +1,339 @@
+//! Locking so several bm processes can use the same CSV at once. Any number of processes can read it, but only one
+//! can change it, and only while nothing is reading it.
+
+use anyhow::{Context, Result, bail};
+use fs4::fs_std::FileExt;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Seconds to wait for another bm process to release the lock
+const ENV_LOCK_TIMEOUT: &str = "BOOKMARK_MANAGER_LOCK_TIMEOUT";
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Any number of processes can read the CSV at the same time
+    Shared,
+    /// Only one process can change the CSV (and commit it) at a time
+    Exclusive,
+}
+
+/// Advisory lock on a lock file next to the CSV.
+/// The lock file is used instead of the CSV itself because rewriting the CSV replaces the file.
+/// The lock is released when this is dropped, and the lock file is removed unless other processes still hold it.
+pub struct CsvLock {
+    /// None if a reader couldn't create the lock file because the directory is read-only
+    file: Option<File>,
+    path: PathBuf,
+    mode: LockMode,
+}
+
+impl CsvLock {
+    pub fn acquire(csv: &str, mode: LockMode) -> Result<Self> {
+        let timeout = match env::var(ENV_LOCK_TIMEOUT) {
+            Ok(secs) => Duration::from_secs(
+                secs.parse()
+                    .with_context(|| format!("{ENV_LOCK_TIMEOUT} must be a number of seconds"))?,
+            ),
+            Err(_) => DEFAULT_LOCK_TIMEOUT,
+        };
+
+        Self::acquire_with_timeout(csv, mode, timeout)
+    }
+
+    fn acquire_with_timeout(csv: &str, mode: LockMode, timeout: Duration) -> Result<Self> {
+        let path = lock_path(csv);
+        let start = Instant::now();
+        let file = loop {
+            let Some(file) = open(&path, mode)? else {
+                // Nobody with the same permissions can change the CSV either
+                return Ok(Self {
+                    file: None,
+                    path,
+                    mode,
+                });
+            };
+
+            wait_for_lock(&file, &path, mode, start, timeout)?;
+
+            // The last process to release the lock removes the lock file, so the one locked may be gone
+            if is_same_file(&file, &path) {
+                break file;
+            }
+        };
+
+        let mut lock = Self {
+            file: Some(file),
+            path,
+            mode,
+        };
+
+        // Only one process can hold an exclusive lock, so it can say who it is for error messages.
+        // Anything left by a process that couldn't clear it (e.g. it was killed) is stale, so it is always cleared.
+        if let Some(file) = &mut lock.file {
+            match mode {
+                LockMode::Shared => {
+                    // Read-only if the directory is
+                    let _ = file.set_len(0);
+                }
+                LockMode::Exclusive => {
+                    file.set_len(0).context("Could not clear lock file")?;
+                    let args = env::args().collect::<Vec<String>>().join(" ");
+                    write!(file, "{} ({args})", process::id())
+                        .context("Could not write to lock file")?;
+                }
+            }
+        }
+
+        Ok(lock)
+    }
+}
+
+/// Open the lock file, creating it if needed.
+/// Readers open it read-only if they can't write to the directory, or return None if it doesn't exist.
+fn open(path: &Path, mode: LockMode) -> Result<Option<File>> {
+    let context = || format!("Could not open lock file {}", path.display());
+
+    match OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+    {
+        Ok(file) => Ok(Some(file)),
+        Err(err)
+            if mode == LockMode::Shared
+                && matches!(
+                    err.kind(),
+                    ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem
+                ) =>
+        {
+            match File::open(path) {
+                Ok(file) => Ok(Some(file)),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err).with_context(context),
+            }
+        }
+        Err(err) => Err(err).with_context(context),
+    }
+}
+
+fn wait_for_lock(
+    file: &File,
+    path: &Path,
+    mode: LockMode,
+    start: Instant,
+    timeout: Duration,
+) -> Result<()> {
+    loop {
+        // Fully qualified since newer versions of std have inherent methods with the same names
+        let locked = match mode {
+            LockMode::Shared => FileExt::try_lock_shared(file),
+            LockMode::Exclusive => FileExt::try_lock_exclusive(file),
+        }
+        .with_context(|| format!("Could not lock {}", path.display()))?;
+
+        if locked {
+            return Ok(());
+        }
+
+        if start.elapsed() >= timeout {
+            bail!(
+                "Timed out after {}s waiting for the lock on the CSV file. It is held by {}. \
+                If that process is no longer running, try again.",
+                timeout.as_secs(),
+                holder(file, path)
+            );
+        }
+
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
+
+/// Whether the lock file at the path is still the one that was opened
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(opened), Ok(current)) => opened.dev() == current.dev() && opened.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+/// Lock files are only removed on Unix, since Windows can't remove a file other processes have open
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, _path: &Path) -> bool {
+    true
+}
+
+/// Who holds the lock. The lock file only says who if the lock is exclusive, since otherwise it can be stale.
+fn holder(file: &File, path: &Path) -> String {
+    // Shared locks are only blocked by an exclusive lock, so getting one means only other readers hold the lock
+    if FileExt::try_lock_shared(file).unwrap_or(false) {
+        let _ = FileExt::unlock(file);
+        return String::from("other bm processes reading it");
+    }
+
+    match fs::read_to_string(path).unwrap_or_default().trim() {
+        "" => String::from("another bm process"),
+        holder => format!("process {holder}"),
+    }
+}
+
+impl Drop for CsvLock {
+    fn drop(&mut self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        // Readers can only remove the lock file if no other process holds the lock.
+        // It is removed before unlocking, so processes waiting for the lock know to open a new one.
+        let removed = cfg!(unix)
+            && (self.mode == LockMode::Exclusive
+                || FileExt::try_lock_exclusive(file).unwrap_or(false))
+            && fs::remove_file(&self.path).is_ok();
+        if !removed && self.mode == LockMode::Exclusive {
+            let _ = file.set_len(0);
+        }
+        let _ = FileExt::unlock(file);
+    }
+}
+
+/// The lock file is the CSV path with ".lock" appended
+pub fn lock_path(csv: &str) -> PathBuf {
+    PathBuf::from(format!("{csv}.lock"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv::lock::{CsvLock, LockMode, lock_path};
+    use std::fs;
+    use std::process;
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn shared_locks_do_not_block() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        let csv = csv.to_str().unwrap();
+
+        let _first = CsvLock::acquire_with_timeout(csv, LockMode::Shared, Duration::ZERO).unwrap();
+        let second = CsvLock::acquire_with_timeout(csv, LockMode::Shared, Duration::ZERO);
+
+        assert!(second.is_ok());
+    }
+
+    #[test]
+    fn exclusive_lock_blocks() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        let csv = csv.to_str().unwrap();
+
+        let first =
+            CsvLock::acquire_with_timeout(csv, LockMode::Exclusive, Duration::ZERO).unwrap();
+
+        let err = CsvLock::acquire_with_timeout(csv, LockMode::Shared, Duration::ZERO)
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string()
+                .contains(format!("held by process {}", process::id()).as_str()),
+            "Unexpected error: {err}"
+        );
+
+        // Available again once released
+        drop(first);
+        assert!(CsvLock::acquire_with_timeout(csv, LockMode::Exclusive, Duration::ZERO).is_ok());
+    }
+
+    #[test]
+    fn stale_holder_is_not_reported() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        let csv = csv.to_str().unwrap();
+        // Left by a process that exited without clearing it
+        fs::write(lock_path(csv), "1 (bm add)").unwrap();
+
+        let _reader = CsvLock::acquire_with_timeout(csv, LockMode::Shared, Duration::ZERO).unwrap();
+        assert_eq!(fs::read_to_string(lock_path(csv)).unwrap(), "");
+
+        let err = CsvLock::acquire_with_timeout(csv, LockMode::Exclusive, Duration::ZERO)
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string()
+                .contains("held by other bm processes reading it"),
+            "Unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn lock_file_is_removed_when_released() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        let csv = csv.to_str().unwrap();
+
+        let writer =
+            CsvLock::acquire_with_timeout(csv, LockMode::Exclusive, Duration::ZERO).unwrap();
+        assert!(lock_path(csv).exists());
+        drop(writer);
+        assert!(!lock_path(csv).exists());
+
+        // Kept until the last reader is done
+        let first = CsvLock::acquire_with_timeout(csv, LockMode::Shared, Duration::ZERO).unwrap();
+        let second = CsvLock::acquire_with_timeout(csv, LockMode::Shared, Duration::ZERO).unwrap();
+        drop(first);
+        assert!(lock_path(csv).exists());
+        drop(second);
+        assert!(!lock_path(csv).exists());
+    }
+
+    #[test]
+    fn waiting_for_removed_lock_file() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        let csv = String::from(csv.to_str().unwrap());
+
+        let first =
+            CsvLock::acquire_with_timeout(&csv, LockMode::Exclusive, Duration::ZERO).unwrap();
+        let waiting = thread::spawn({
+            let csv = csv.clone();
+            move || {
+                CsvLock::acquire_with_timeout(&csv, LockMode::Exclusive, Duration::from_secs(10))
+            }
+        });
+        thread::sleep(Duration::from_millis(200));
+        drop(first);
+
+        // The waiting process holds the new lock file rather than the removed one
+        let _second = waiting.join().unwrap().unwrap();
+        assert!(lock_path(&csv).exists());
+        assert!(CsvLock::acquire_with_timeout(&csv, LockMode::Shared, Duration::ZERO).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reader_in_read_only_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        let csv = csv.to_str().unwrap();
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o555)).unwrap();
+
+        let reader = CsvLock::acquire_with_timeout(csv, LockMode::Shared, Duration::ZERO).map(drop);
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(reader.is_ok());
+    }
+}
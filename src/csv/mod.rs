@@ -1,21 +1,134 @@
+mod change;
 mod csv_line_reader;
 mod csv_line_writer;
+mod escape;
+mod lock;
+mod schema;
 
 use anyhow::{Context, Result};
-use std::fs::File;
-use std::io::Write;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
 use std::path::Path;
+use tempfile::NamedTempFile;
 
 use crate::cli_output::utils::print_success;
-pub use csv_line_reader::CsvLineReader;
-pub use csv_line_writer::CsvLineWriter;
-
-const ORDERED_HEADERS: [&str; 3] = ["URL", "DESCRIPTION", "TAGS"];
+pub use change::CsvChange;
+pub use csv_line_reader::{CsvLineReader, ParseMode, parse_line};
+pub use csv_line_writer::{CsvLineWriter, format_line};
+pub use lock::{CsvLock, LockMode, lock_path};
+pub use schema::Schema;
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Line {
     pub url: String,
     pub description: String,
     pub tags: Vec<String>,
+    /// Only stored if the CSV has an ADDED column
+    pub added: Option<String>,
+    /// Only stored if the CSV has a NOTES column
+    pub notes: Option<String>,
+    /// Only stored if the CSV has an ARCHIVED column
+    pub archived: bool,
+}
+
+#[cfg(test)]
+impl Line {
+    /// A bookmark without the optional columns
+    pub fn new(url: &str, description: &str, tags: &[&str]) -> Self {
+        Line {
+            url: String::from(url),
+            description: String::from(description),
+            tags: tags.iter().map(|tag| String::from(*tag)).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Read every bookmark for a command that doesn't change the file. The CSV is only locked while it is read, so the
+/// caller can take its time with them (e.g. prompting the user) without blocking other commands.
+/// Never call this while holding an exclusive lock, since it would wait for itself.
+pub fn read_lines(csv: &str, mode: ParseMode) -> Result<Vec<Line>> {
+    let _lock = CsvLock::acquire(csv, LockMode::Shared)?;
+    CsvLineReader::new(csv)?.with_mode(mode).collect()
+}
+
+/// The current time in the format of the ADDED column
+pub fn added_now() -> String {
+    format_added(Utc::now())
+}
+
+/// Format a time for the ADDED column
+pub fn format_added(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Replace the contents of the CSV with the given lines (headers are written first).
+/// Values are always escaped, which upgrades a file written before escaping existed.
+pub fn rewrite_csv(csv_path: &str, schema: &Schema, lines: &[Line]) -> Result<()> {
+    let schema = schema.clone().with_escaping(true);
+    let schema = &schema;
+    replace_csv(csv_path, |writer| {
+        writeln!(writer, "{}", schema.header()).context("Couldn't write headers to CSV file")?;
+        for line in lines {
+            writeln!(writer, "{}", format_line(schema, line))
+                .context("Couldn't write bookmark to CSV file")?;
+        }
+        Ok(())
+    })
+}
+
+/// Replace the contents of the CSV with the given rows (including the header) exactly as they are, including their
+/// line terminators. Used when some rows can't be parsed, so they can't be written from a `Line`.
+pub fn rewrite_csv_rows(csv_path: &str, rows: &[String]) -> Result<()> {
+    replace_csv(csv_path, |writer| {
+        for row in rows {
+            write!(writer, "{row}").context("Couldn't write to CSV file")?;
+        }
+        Ok(())
+    })
+}
+
+/// The contents are written to a temporary file in the same directory, which is then renamed over the CSV.
+/// That way the CSV is never left half written if the program is interrupted.
+fn replace_csv(
+    csv_path: &str,
+    write: impl FnOnce(&mut BufWriter<&mut File>) -> Result<()>,
+) -> Result<()> {
+    // Follow symlinks so the link isn't replaced with a regular file
+    let path = fs::canonicalize(csv_path).context("Could not find CSV file")?;
+    let dir = path.parent().context("CSV file has no parent directory")?;
+
+    let mut file = NamedTempFile::new_in(dir).context("Could not create temporary CSV file")?;
+    {
+        let mut writer = BufWriter::new(file.as_file_mut());
+        write(&mut writer)?;
+        writer
+            .flush()
+            .context("Couldn't write bookmarks to CSV file")?;
+    }
+
+    // Temporary files are only readable by the owner so keep the original permissions
+    let permissions = fs::metadata(&path)
+        .context("Could not read CSV file permissions")?
+        .permissions();
+    file.as_file()
+        .set_permissions(permissions)
+        .context("Could not set CSV file permissions")?;
+
+    // Make sure the contents are on disk before the rename makes them visible
+    file.as_file()
+        .sync_all()
+        .context("Couldn't sync CSV file to disk")?;
+    file.persist(&path).context("Couldn't replace CSV file")?;
+
+    // The rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .context("Couldn't sync CSV directory to disk")?;
+
+    Ok(())
 }
 
 /// If the CSV already exists, do nothing.  Otherwise create it with headers
@@ -23,7 +136,7 @@ pub fn create_csv(csv_path: &str) -> Result<bool> {
     if !csv_exists(csv_path) {
         let path = Path::new(csv_path);
         let mut file = File::create(path).context("Couldn't create CSV file")?;
-        writeln!(file, "{}", ORDERED_HEADERS.join("|"))
+        writeln!(file, "{}", Schema::required().header())
             .context("Couldn't write headers to new CSV file")?;
         print_success("CSV file created");
         return Ok(true);
@@ -36,3 +149,47 @@ pub fn csv_exists(csv_path: &str) -> bool {
     let path = Path::new(csv_path);
     path.exists()
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::csv::{Line, Schema, rewrite_csv};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn rewrite_replaces_contents() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        fs::write(&csv, "URL|DESCRIPTION|TAGS\nhttps://bing.com|Bing|\n").unwrap();
+
+        rewrite_csv(
+            csv.to_str().unwrap(),
+            &Schema::required(),
+            &[Line::new("https://google.com", "Google", &["Search"])],
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&csv).unwrap(),
+            "URL|DESCRIPTION|TAGS|#escaped\nhttps://google.com|Google|Search\n"
+        );
+        // The temporary file was renamed, not left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rewrite_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        fs::write(&csv, "URL|DESCRIPTION|TAGS\n").unwrap();
+        fs::set_permissions(&csv, fs::Permissions::from_mode(0o664)).unwrap();
+
+        rewrite_csv(csv.to_str().unwrap(), &Schema::required(), &[]).unwrap();
+
+        let mode = fs::metadata(&csv).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o664);
+    }
+}
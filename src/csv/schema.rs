@@ -0,0 +1,200 @@
+//! The columns of a bookmarks file. URL, DESCRIPTION and TAGS are always there. Newer files can have more columns
+//! (added by `bm migrate`), and the header ends with a marker once the values are escaped.
+
+use anyhow::{Context, Result, bail, ensure};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use super::escape::{COLUMN_SEPARATOR, ESCAPED_MARKER};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Url,
+    Description,
+    Tags,
+    /// When the bookmark was added (RFC 3339)
+    Added,
+    Notes,
+    Archived,
+}
+
+impl Column {
+    /// Name of the column in the header row
+    pub fn header(&self) -> &'static str {
+        match self {
+            Column::Url => "URL",
+            Column::Description => "DESCRIPTION",
+            Column::Tags => "TAGS",
+            Column::Added => "ADDED",
+            Column::Notes => "NOTES",
+            Column::Archived => "ARCHIVED",
+        }
+    }
+
+    fn from_header(header: &str) -> Option<Self> {
+        ALL_COLUMNS
+            .into_iter()
+            .find(|column| column.header().eq_ignore_ascii_case(header.trim()))
+    }
+}
+
+/// Every bookmarks file has these columns. Files created before the schema was extensible only have these.
+const REQUIRED_COLUMNS: [Column; 3] = [Column::Url, Column::Description, Column::Tags];
+
+/// All the known columns in the order `bm migrate` writes them
+const ALL_COLUMNS: [Column; 6] = [
+    Column::Url,
+    Column::Description,
+    Column::Tags,
+    Column::Added,
+    Column::Notes,
+    Column::Archived,
+];
+
+/// The columns of a bookmarks file, in order, as given by its header row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    columns: Vec<Column>,
+    /// Whether values are backslash escaped. Files written before escaping existed don't have the marker in their
+    /// header, so their values are read literally.
+    escaped: bool,
+}
+
+impl Schema {
+    /// Schema of a newly created bookmarks file
+    pub fn required() -> Self {
+        Self {
+            columns: REQUIRED_COLUMNS.to_vec(),
+            escaped: true,
+        }
+    }
+
+    /// Schema with every known column
+    pub fn latest() -> Self {
+        Self {
+            columns: ALL_COLUMNS.to_vec(),
+            escaped: true,
+        }
+    }
+
+    /// The same columns, with or without escaping
+    pub fn with_escaping(mut self, escaped: bool) -> Self {
+        self.escaped = escaped;
+        self
+    }
+
+    /// Read the schema from the header row of the CSV
+    pub fn read(csv: &str) -> Result<Self> {
+        let file = File::open(csv).context("Could not open CSV file")?;
+        let mut header = String::new();
+        BufReader::new(file)
+            .read_line(&mut header)
+            .context("Could not read CSV header")?;
+
+        Self::parse_header(header.trim_end_matches(['\n', '\r']))
+    }
+
+    pub fn parse_header(header: &str) -> Result<Self> {
+        let mut names = header.split(COLUMN_SEPARATOR).collect::<Vec<&str>>();
+        let escaped = names
+            .last()
+            .is_some_and(|name| name.trim() == ESCAPED_MARKER);
+        if escaped {
+            names.pop();
+        }
+
+        let mut columns = Vec::new();
+        for name in names {
+            let column = match Column::from_header(name) {
+                Some(column) => column,
+                None => bail!("Unknown column in CSV header: {name}"),
+            };
+            ensure!(
+                !columns.contains(&column),
+                "Duplicate column in CSV header: {name}"
+            );
+            columns.push(column);
+        }
+
+        for column in REQUIRED_COLUMNS {
+            ensure!(
+                columns.contains(&column),
+                "CSV header is missing the {} column: {header}",
+                column.header()
+            );
+        }
+
+        Ok(Self { columns, escaped })
+    }
+
+    /// The header row (without the new line)
+    pub fn header(&self) -> String {
+        match self.escaped {
+            true => format!("{}{COLUMN_SEPARATOR}{ESCAPED_MARKER}", self.column_names()),
+            false => self.column_names(),
+        }
+    }
+
+    /// The names of the columns, separated like in the header row but without the escaping marker
+    pub fn column_names(&self) -> String {
+        self.columns
+            .iter()
+            .map(|column| column.header())
+            .collect::<Vec<&str>>()
+            .join(COLUMN_SEPARATOR.to_string().as_str())
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn is_escaped(&self) -> bool {
+        self.escaped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv::schema::{Column, Schema};
+
+    #[test]
+    fn required_header() {
+        let schema = Schema::parse_header("URL|DESCRIPTION|TAGS|#escaped").unwrap();
+
+        assert_eq!(schema, Schema::required());
+        assert_eq!(schema.column_names(), "URL|DESCRIPTION|TAGS");
+    }
+
+    #[test]
+    fn header_without_marker_is_not_escaped() {
+        let schema = Schema::parse_header("URL|DESCRIPTION|TAGS").unwrap();
+
+        assert!(!schema.is_escaped());
+        assert_eq!(schema, Schema::required().with_escaping(false));
+        assert_eq!(schema.header(), "URL|DESCRIPTION|TAGS");
+    }
+
+    #[test]
+    fn extra_columns_in_any_order() {
+        let schema = Schema::parse_header("URL|added|DESCRIPTION|TAGS|NOTES").unwrap();
+
+        assert_eq!(
+            schema.columns(),
+            [
+                Column::Url,
+                Column::Added,
+                Column::Description,
+                Column::Tags,
+                Column::Notes
+            ]
+        );
+        assert_eq!(schema.header(), "URL|ADDED|DESCRIPTION|TAGS|NOTES");
+    }
+
+    #[test]
+    fn invalid_headers() {
+        assert!(Schema::parse_header("URL|DESCRIPTION").is_err());
+        assert!(Schema::parse_header("URL|DESCRIPTION|TAGS|COLOR").is_err());
+        assert!(Schema::parse_header("URL|DESCRIPTION|TAGS|URL").is_err());
+    }
+}
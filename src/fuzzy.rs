@@ -0,0 +1,175 @@
+//! Typo tolerant search. Each word of the query is compared to every word of the URL, description and tags and the
+//! most similar one is used. A bookmark matches if every query word is similar enough to one of its words and
+//! bookmarks are ranked by the average similarity.
+
+use std::ops::Range;
+use strsim::normalized_damerau_levenshtein;
+
+use crate::csv::Line;
+use crate::query::{QueryMatch, merge_ranges};
+
+/// How similar (0 to 1) a word must be to a query word. One typo in a 7 letter word is about 0.86
+const MIN_SIMILARITY: f64 = 0.7;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Url,
+    Description,
+    Tag,
+}
+
+/// A word (a run of letters and numbers) in a bookmark
+struct Word {
+    field: Field,
+    /// Lowercase characters
+    chars: Vec<char>,
+    /// Byte offset in the field of each character plus the end of the word
+    offsets: Vec<usize>,
+}
+
+/// The score (0 to 1, higher is better) and where to highlight if the bookmark matches
+pub fn find(query: &str, line: &Line) -> Option<(f64, QueryMatch)> {
+    let query_words = words(query, Field::Description)
+        .into_iter()
+        .map(|word| word.chars)
+        .collect::<Vec<Vec<char>>>();
+    if query_words.is_empty() {
+        return None;
+    }
+
+    let mut line_words = words(&line.url, Field::Url);
+    line_words.extend(words(&line.description, Field::Description));
+    for tag in &line.tags {
+        line_words.extend(words(tag, Field::Tag));
+    }
+
+    let mut total = 0.0;
+    let mut matched = QueryMatch::default();
+
+    for query_word in &query_words {
+        let scored = line_words
+            .iter()
+            .map(|word| (similarity(query_word, &word.chars), word))
+            .collect::<Vec<((f64, Range<usize>), &Word)>>();
+
+        let best = scored
+            .iter()
+            .map(|((score, _), _)| *score)
+            .fold(0.0, f64::max);
+        if best < MIN_SIMILARITY {
+            return None;
+        }
+        total += best;
+
+        // Highlight every word that is the best match, e.g. the same word in the URL and description
+        for ((_, range), word) in scored.iter().filter(|((score, _), _)| *score == best) {
+            let bytes = word.offsets[range.start]..word.offsets[range.end];
+            match word.field {
+                Field::Url => matched.url.push(bytes),
+                Field::Description => matched.description.push(bytes),
+                Field::Tag => {}
+            }
+        }
+    }
+
+    let matched = QueryMatch {
+        url: merge_ranges(matched.url),
+        description: merge_ranges(matched.description),
+    };
+    Some((total / query_words.len() as f64, matched))
+}
+
+/// Similarity (0 to 1) of a query word to a word and the characters of the word to highlight
+fn similarity(query: &[char], word: &[char]) -> (f64, Range<usize>) {
+    if query == word {
+        return (1.0, 0..word.len());
+    }
+
+    // Partially typed words are a good match, better the more of the word is typed
+    if let Some(start) = word.windows(query.len()).position(|window| window == query) {
+        let typed = query.len() as f64 / word.len() as f64;
+        return (0.8 + 0.2 * typed, start..start + query.len());
+    }
+
+    let query = query.iter().collect::<String>();
+    let word_str = word.iter().collect::<String>();
+    (
+        normalized_damerau_levenshtein(&query, &word_str),
+        0..word.len(),
+    )
+}
+
+/// Split the text into lowercase words
+fn words(text: &str, field: Field) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut word: Option<Word> = None;
+
+    for (offset, c) in text.char_indices() {
+        if c.is_alphanumeric() {
+            let current = word.get_or_insert_with(|| Word {
+                field,
+                chars: Vec::new(),
+                offsets: Vec::new(),
+            });
+            // Keep one character per character so offsets line up (some lowercase to more than one)
+            current.chars.push(c.to_lowercase().next().unwrap_or(c));
+            current.offsets.push(offset);
+        } else if let Some(mut current) = word.take() {
+            current.offsets.push(offset);
+            words.push(current);
+        }
+    }
+
+    if let Some(mut current) = word {
+        current.offsets.push(text.len());
+        words.push(current);
+    }
+
+    words
+}
+
+#[cfg(test)]
+// The expected highlights are often a single range
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use crate::csv::Line;
+    use crate::fuzzy::find;
+
+    #[test]
+    fn typos() {
+        let kubernetes = Line::new("https://kubernetes.io", "Kubernetes docs", &[]);
+
+        let (score, matched) = find("kuberentes", &kubernetes).unwrap();
+        assert!(score > 0.85, "{score}");
+        assert_eq!(matched.url, vec![8..18]);
+        assert_eq!(matched.description, vec![0..10]);
+
+        assert!(find("kubernetes dcos", &kubernetes).is_some());
+        assert!(find("kuberentes python", &kubernetes).is_none());
+    }
+
+    #[test]
+    fn partial_words_highlight_matched_characters() {
+        let (score, matched) =
+            find("DOC", &Line::new("https://a.com", "Kubernetes Docs", &[])).unwrap();
+
+        assert!(score > 0.9 && score < 1.0, "{score}");
+        assert_eq!(matched.description, vec![11..14]);
+    }
+
+    #[test]
+    fn tags() {
+        assert!(find("opps", &Line::new("https://a.com", "A", &["ops"])).is_some());
+    }
+
+    #[test]
+    fn ranking() {
+        let exact = find("rust", &Line::new("https://rust-lang.org", "Rust", &[])).unwrap();
+        let partial = find("rust", &Line::new("https://a.com", "Rustacean", &[])).unwrap();
+        let typo = find("rust", &Line::new("https://rest.com", "Rest", &[])).unwrap();
+
+        assert!(exact.0 > partial.0);
+        assert!(partial.0 > typo.0);
+        assert!(find("rust", &Line::new("https://a.com", "Go", &[])).is_none());
+    }
+}
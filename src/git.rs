@@ -1,36 +1,58 @@
 use crate::cli_output::utils::print_warning;
-use anyhow::{Context, Result};
-use git2::{Commit, IndexAddOption, ObjectType, Repository};
-use std::path::Path;
+use crate::csv::lock_path;
+use anyhow::{Context, Result, bail};
+use git2::{Commit, IndexAddOption, ObjectType, Oid, Repository, Sort};
+use std::path::{Path, PathBuf};
 
 pub struct Git {
     repo: Repository,
+    /// Lock file of the CSV relative to the root of the repo. It should never be committed.
+    lock_file: Option<PathBuf>,
 }
 
 impl Git {
     pub fn new(csv: &str) -> Option<Self> {
+        let git = Self::find(csv);
+        if git.is_none() {
+            print_warning(
+                "It appears the CSV file is not in a git repo. Use --no-commit to suppress this message",
+            );
+        }
+        git
+    }
+
+    /// The git repo the CSV is in, if there is one. Unlike `new`, there is no warning if there isn't.
+    pub fn find(csv: &str) -> Option<Self> {
         let mut dir = Path::new(csv);
 
         // Traverse the directory tree looking for the git repo
         let repo = loop {
-            match dir.parent() {
-                None => {
-                    print_warning(
-                        "It appears the CSV file is not in a git repo. Use --no-commit to suppress this message",
-                    );
-                    return None;
-                }
-                Some(new_dir) => {
-                    dir = new_dir;
-                    let repo_result = Repository::open(dir);
-                    if let Ok(repo) = repo_result {
-                        break repo;
-                    }
-                }
+            dir = dir.parent()?;
+            if let Ok(repo) = Repository::open(dir) {
+                break repo;
             }
         };
 
-        Some(Self { repo })
+        let lock_file = relative_to_workdir(&repo, &lock_path(csv));
+
+        Some(Self { repo, lock_file })
+    }
+
+    /// Open the git repo unless the user doesn't want to commit changes.
+    /// Fails if there are uncommitted changes since it wouldn't be safe to commit on top of them.
+    pub fn open_clean(commit: bool, csv: &str) -> Result<Option<Self>> {
+        let git = match commit {
+            false => None,
+            true => Self::new(csv),
+        };
+
+        if let Some(git) = &git {
+            if !git.is_clean()? {
+                bail!("Git repo has uncommitted changes");
+            }
+        }
+
+        Ok(git)
     }
 
     pub fn is_clean(&self) -> Result<bool> {
@@ -39,18 +61,15 @@ impl Git {
             .statuses(None)
             .context("Could not get git status")?;
         // https://github.com/rust-lang/git2-rs/blob/master/examples/status.rs#L174
-        let is_dirty = statuses
-            .iter()
-            .any(|e| e.status() != git2::Status::CURRENT && e.status() != git2::Status::IGNORED);
+        let is_dirty = statuses.iter().any(|e| {
+            e.status() != git2::Status::CURRENT
+                && e.status() != git2::Status::IGNORED
+                && !self.is_lock_file(e.path_bytes())
+        });
 
         Ok(!is_dirty)
     }
 
-    pub fn add_and_commit_bookmark(&self, url: &str, description: &str) -> Result<()> {
-        self.add_and_commit(format!("Add bookmark for {url} - {description}").as_str())?;
-        Ok(())
-    }
-
     // https://zsiciarz.github.io/24daysofrust/book/vol2/day16.html
     // https://github.com/rust-lang/git2-rs/blob/master/examples/add.rs#L71
     pub fn add_and_commit(&self, msg: &str) -> Result<()> {
@@ -59,7 +78,16 @@ impl Git {
             let mut index = self.repo.index()?;
 
             // Since we check to make sure that there are no previous uncommitted changes, it is safe to add all
-            index.add_all(["*"].iter(), IndexAddOption::DEFAULT, None)?;
+            index.add_all(
+                ["*"].iter(),
+                IndexAddOption::DEFAULT,
+                Some(&mut |path: &Path, _: &[u8]| match self
+                    .is_lock_file(path.as_os_str().as_encoded_bytes())
+                {
+                    true => 1,
+                    false => 0,
+                }),
+            )?;
 
             // I don't know why we need to double write, but it is necessary in order for the commit to go through
             index.write()?;
@@ -83,9 +111,59 @@ impl Git {
         Ok(())
     }
 
+    /// Call `f` with the commit time (seconds since the epoch) and contents of every committed version of the CSV,
+    /// oldest first. Commits that didn't change it are skipped.
+    pub fn for_each_version(&self, csv: &str, mut f: impl FnMut(i64, &str)) -> Result<()> {
+        let path = relative_to_workdir(&self.repo, Path::new(csv))
+            .context("CSV file is not in the git repo's working directory")?;
+
+        let mut revwalk = self.repo.revwalk().context("Could not read git history")?;
+        // A repo without commits has no history
+        if revwalk.push_head().is_err() {
+            return Ok(());
+        }
+        revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME | Sort::REVERSE)?;
+
+        let mut last_blob: Option<Oid> = None;
+        for oid in revwalk {
+            let commit = self.repo.find_commit(oid?)?;
+            let Ok(entry) = commit.tree()?.get_path(&path) else {
+                continue;
+            };
+            if last_blob == Some(entry.id()) {
+                continue;
+            }
+            last_blob = Some(entry.id());
+
+            let blob = self.repo.find_blob(entry.id())?;
+            f(
+                commit.time().seconds(),
+                String::from_utf8_lossy(blob.content()).as_ref(),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn is_lock_file(&self, path: &[u8]) -> bool {
+        self.lock_file
+            .as_ref()
+            .is_some_and(|lock_file| lock_file.as_os_str().as_encoded_bytes() == path)
+    }
+
     fn find_last_commit(&self) -> Result<Commit<'_>, git2::Error> {
         let obj = self.repo.head()?.resolve()?.peel(ObjectType::Commit)?;
         obj.into_commit()
             .map_err(|_| git2::Error::from_str("Couldn't find last commit"))
     }
 }
+
+/// Git reports paths relative to the root of the repo
+fn relative_to_workdir(repo: &Repository, path: &Path) -> Option<PathBuf> {
+    // The lock file may not exist yet, so only canonicalize the directory it is in
+    let dir = path.parent()?.canonicalize().ok()?;
+    let workdir = repo.workdir()?.canonicalize().ok()?;
+    let relative = dir.strip_prefix(workdir).ok()?.join(path.file_name()?);
+
+    Some(relative)
+}
@@ -0,0 +1,103 @@
+//! The little HTML handling bookmarks need: escaping text for exported bookmark files and decoding the character
+//! references in imported bookmark files and page titles.
+
+/// Escape text so it can be used in HTML content or a double quoted attribute
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Replace HTML character references (e.g. `&amp;` or `&#39;`) with the characters they represent.
+/// Only the named references that show up in bookmark exports and page titles are supported.
+/// Unknown references are left as is.
+pub fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| decode_entity(&rest[1..end]).map(|c| (c, end)));
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Decode the reference between the `&` and `;`
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(hex) = entity
+        .strip_prefix("#x")
+        .or_else(|| entity.strip_prefix("#X"))
+    {
+        return u32::from_str_radix(hex, 16).ok().and_then(char::from_u32);
+    }
+    if let Some(decimal) = entity.strip_prefix('#') {
+        return decimal.parse().ok().and_then(char::from_u32);
+    }
+
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::{decode_entities, escape};
+
+    #[test]
+    fn escape_round_trip() {
+        let text = "<a href=\"x\">Tom & Jerry's</a>";
+        assert_eq!(
+            escape(text),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+        assert_eq!(decode_entities(escape(text).as_str()), text);
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(
+            decode_entities("Tom &amp; Jerry&#39;s &lt;b&gt; &#x263A;"),
+            "Tom & Jerry's <b> \u{263A}"
+        );
+    }
+
+    #[test]
+    fn unknown_entities_are_kept() {
+        assert_eq!(decode_entities("a & b &bogus; c&"), "a & b &bogus; c&");
+    }
+}
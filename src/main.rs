@@ -4,17 +4,38 @@ use std::env;
 
 use crate::cli_output::utils::exit_error;
 use crate::commands::add::add;
+use crate::commands::check::check;
+use crate::commands::dedupe::dedupe;
+use crate::commands::edit::edit;
+use crate::commands::export::export;
+use crate::commands::import::import;
+use crate::commands::linkcheck::linkcheck;
+use crate::commands::migrate::migrate;
+use crate::commands::open::open;
+use crate::commands::remove::remove;
 use crate::commands::search::search;
 use crate::commands::tags::tags;
+use crate::csv::ParseMode;
 use config::Command;
 
+mod canonical_url;
 mod cli_output;
 mod commands;
 mod config;
 mod csv;
+mod fuzzy;
 mod git;
+mod html;
+mod netscape;
+mod page_title;
+mod query;
+mod settings;
+mod suggest;
+mod tag_expr;
 
 const ENV_CSV: &str = "BOOKMARK_MANAGER_CSV";
+/// Set to 1 or true to always use --strict
+const ENV_STRICT: &str = "BOOKMARK_MANAGER_STRICT";
 
 fn main() -> Result<()> {
     let opt = config::Opts::parse();
@@ -22,16 +43,33 @@ fn main() -> Result<()> {
     let csv = env::var(ENV_CSV)
         .unwrap_or_else(|_| panic!("Environmental variable {ENV_CSV} must be set"));
 
-    // The add command will create the CSV, the others will fail if it does not exit
-    if let Command::Add(_) = opt.cmd {
-    } else if !csv::csv_exists(csv.as_str()) {
+    // The add and import commands will create the CSV, the others will fail if it does not exit
+    if !matches!(opt.cmd, Command::Add(_) | Command::Import(_)) && !csv::csv_exists(csv.as_str()) {
         exit_error("The CSV file doesn't exist.  It will be created the first time you run 'add'.");
     }
 
+    // Only used by the commands that don't change the file
+    let strict = opt.strict
+        || env::var(ENV_STRICT)
+            .is_ok_and(|strict| strict == "1" || strict.eq_ignore_ascii_case("true"));
+    let parse_mode = match strict {
+        true => ParseMode::Strict,
+        false => ParseMode::Lenient,
+    };
+
     match opt.cmd {
         Command::Add(add_opts) => add(&add_opts, &csv)?,
-        Command::Search(search_opts) => search(&search_opts, &csv)?,
-        Command::Tags(tags_opts) => tags(&tags_opts, &csv)?,
+        Command::Edit(edit_opts) => edit(&edit_opts, &csv)?,
+        Command::Remove(remove_opts) => remove(&remove_opts, &csv)?,
+        Command::Export(export_opts) => export(&export_opts, &csv, parse_mode)?,
+        Command::Import(import_opts) => import(&import_opts, &csv)?,
+        Command::Open(open_opts) => open(&open_opts, &csv, parse_mode)?,
+        Command::Search(search_opts) => search(&search_opts, &csv, parse_mode)?,
+        Command::Migrate(migrate_opts) => migrate(&migrate_opts, &csv)?,
+        Command::Tags(tags_opts) => tags(&tags_opts, &csv, parse_mode)?,
+        Command::Dedupe(dedupe_opts) => dedupe(&dedupe_opts, &csv)?,
+        Command::Check(check_opts) => check(&check_opts, &csv)?,
+        Command::Linkcheck(linkcheck_opts) => linkcheck(&linkcheck_opts, &csv, parse_mode)?,
     }
 
     Ok(())
@@ -0,0 +1,211 @@
+//! The Netscape bookmark file format is what browsers (Chrome, Firefox, Edge, Safari) use to import and export bookmarks.
+//! <https://learn.microsoft.com/en-us/previous-versions/windows/internet-explorer/ie-developer/platform-apis/aa753582(v=vs.85)>
+
+use regex::Regex;
+
+use crate::html::{decode_entities, escape};
+
+pub struct NetscapeBookmark {
+    pub url: String,
+    pub title: String,
+    /// Names of the folders the bookmark is in (outermost first) followed by any TAGS attribute values
+    pub tags: Vec<String>,
+    /// Seconds since the Unix epoch
+    pub add_date: Option<i64>,
+}
+
+pub struct NetscapeFolder {
+    pub name: String,
+    pub folders: Vec<NetscapeFolder>,
+    pub bookmarks: Vec<NetscapeBookmark>,
+}
+
+/// Parse every bookmark out of a Netscape bookmark file.
+///
+/// Like the browsers themselves, this is lenient and doesn't require well-formed HTML.
+/// Each folder (`<H3>`) is open until the next `</DL>` which closes the list of bookmarks in the folder.
+pub fn parse(html: &str) -> Vec<NetscapeBookmark> {
+    let token_re = Regex::new(r"(?is)<H3[^>]*>(.*?)</H3>|<A\s([^>]*)>(.*?)</A>|</DL>").unwrap();
+    let attr_re = Regex::new(r#"(?is)([A-Z_]+)\s*=\s*"([^"]*)""#).unwrap();
+
+    let mut folders: Vec<String> = Vec::new();
+    let mut bookmarks = Vec::new();
+
+    for token in token_re.captures_iter(html) {
+        if let Some(folder) = token.get(1) {
+            folders.push(clean_text(folder.as_str()));
+        } else if let (Some(attrs), Some(title)) = (token.get(2), token.get(3)) {
+            let mut url = None;
+            let mut add_date = None;
+            let mut tags = folders.clone();
+
+            for attr in attr_re.captures_iter(attrs.as_str()) {
+                let value = decode_entities(&attr[2]);
+                match attr[1].to_uppercase().as_str() {
+                    "HREF" => url = Some(value),
+                    "ADD_DATE" => add_date = value.trim().parse().ok(),
+                    // Firefox exports tags as a comma separated list
+                    "TAGS" => tags.extend(
+                        value
+                            .split(',')
+                            .map(|tag| tag.trim())
+                            .filter(|tag| !tag.is_empty())
+                            .map(String::from),
+                    ),
+                    _ => {}
+                }
+            }
+
+            if let Some(url) = url {
+                bookmarks.push(NetscapeBookmark {
+                    url: url.trim().to_string(),
+                    title: clean_text(title.as_str()),
+                    tags,
+                    add_date,
+                });
+            }
+        } else {
+            folders.pop();
+        }
+    }
+
+    bookmarks
+}
+
+/// Write a Netscape bookmark file. The root folder is the top level list of bookmarks (its name is the title).
+pub fn to_html(root: &NetscapeFolder) -> String {
+    let mut html = format!(
+        "<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file.
+     It will be read and overwritten.
+     DO NOT EDIT! -->
+<META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">
+<TITLE>{name}</TITLE>
+<H1>{name}</H1>
+",
+        name = escape(&root.name)
+    );
+    write_list(&mut html, root, 0);
+
+    html
+}
+
+/// Write the contents of a folder. Browsers expect the unclosed `<DT>` and `<p>` tags.
+fn write_list(html: &mut String, folder: &NetscapeFolder, depth: usize) {
+    let indent = "    ".repeat(depth);
+    html.push_str(format!("{indent}<DL><p>\n").as_str());
+
+    for sub_folder in &folder.folders {
+        html.push_str(format!("{indent}    <DT><H3>{}</H3>\n", escape(&sub_folder.name)).as_str());
+        write_list(html, sub_folder, depth + 1);
+    }
+
+    for bookmark in &folder.bookmarks {
+        let mut attrs = format!("HREF=\"{}\"", escape(&bookmark.url));
+        if let Some(add_date) = bookmark.add_date {
+            attrs.push_str(format!(" ADD_DATE=\"{add_date}\"").as_str());
+        }
+        if !bookmark.tags.is_empty() {
+            attrs.push_str(format!(" TAGS=\"{}\"", escape(&bookmark.tags.join(","))).as_str());
+        }
+        html.push_str(
+            format!(
+                "{indent}    <DT><A {attrs}>{}</A>\n",
+                escape(&bookmark.title)
+            )
+            .as_str(),
+        );
+    }
+
+    html.push_str(format!("{indent}</DL><p>\n").as_str());
+}
+
+/// Decode the text and collapse white space (including new lines) into single spaces
+fn clean_text(text: &str) -> String {
+    decode_entities(text)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::netscape::{NetscapeBookmark, NetscapeFolder, parse, to_html};
+
+    const EXPORT: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks</H1>
+<DL><p>
+    <DT><H3 ADD_DATE="1626950000" PERSONAL_TOOLBAR_FOLDER="true">Bookmarks bar</H3>
+    <DL><p>
+        <DT><A HREF="https://google.com/?q=a|b" ADD_DATE="1626950001">Google &amp; friends</A>
+        <DT><H3>Rust</H3>
+        <DL><p>
+            <DT><A HREF="https://www.rust-lang.org/" ADD_DATE="1626950002" TAGS="lang,  programming">Rust
+            Programming Language</A>
+        </DL><p>
+        <DT><A HREF="https://github.com">GitHub</A>
+    </DL><p>
+    <DT><A HREF="https://example.com"></A>
+</DL><p>
+"#;
+
+    #[test]
+    fn folders_become_tags() {
+        let bookmarks = parse(EXPORT);
+
+        assert_eq!(bookmarks.len(), 4);
+        assert_eq!(bookmarks[0].tags, vec!["Bookmarks bar"]);
+        assert_eq!(
+            bookmarks[1].tags,
+            vec!["Bookmarks bar", "Rust", "lang", "programming"]
+        );
+        assert_eq!(bookmarks[2].tags, vec!["Bookmarks bar"]);
+        assert!(bookmarks[3].tags.is_empty());
+    }
+
+    #[test]
+    fn attributes_and_titles() {
+        let bookmarks = parse(EXPORT);
+
+        assert_eq!(bookmarks[0].url, "https://google.com/?q=a|b");
+        assert_eq!(bookmarks[0].title, "Google & friends");
+        assert_eq!(bookmarks[0].add_date, Some(1626950001));
+        assert_eq!(bookmarks[1].title, "Rust Programming Language");
+        assert_eq!(bookmarks[2].add_date, None);
+        assert_eq!(bookmarks[3].title, "");
+    }
+
+    #[test]
+    fn write_and_parse() {
+        let root = NetscapeFolder {
+            name: String::from("Bookmarks"),
+            folders: vec![NetscapeFolder {
+                name: String::from("Search & Find"),
+                folders: Vec::new(),
+                bookmarks: vec![NetscapeBookmark {
+                    url: String::from("https://google.com/?q=\"a\"&b=<c>"),
+                    title: String::from("Google <Search>"),
+                    tags: Vec::new(),
+                    add_date: Some(1626950001),
+                }],
+            }],
+            bookmarks: vec![NetscapeBookmark {
+                url: String::from("https://github.com"),
+                title: String::from("GitHub"),
+                tags: vec![String::from("code"), String::from("git")],
+                add_date: None,
+            }],
+        };
+
+        let bookmarks = parse(to_html(&root).as_str());
+
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].url, "https://google.com/?q=\"a\"&b=<c>");
+        assert_eq!(bookmarks[0].title, "Google <Search>");
+        assert_eq!(bookmarks[0].tags, vec!["Search & Find"]);
+        assert_eq!(bookmarks[0].add_date, Some(1626950001));
+        assert_eq!(bookmarks[1].tags, vec!["code", "git"]);
+    }
+}
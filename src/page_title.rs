@@ -0,0 +1,96 @@
+//! Fetch the title of a web page so it can be used as a bookmark's description
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::io::Read;
+use std::time::Duration;
+use ureq::AgentBuilder;
+
+use crate::html::decode_entities;
+
+/// Adding a bookmark shouldn't hang on a slow site
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The title is in the `<head>` so there is no need to download all of a large page
+const MAX_PAGE_BYTES: u64 = 1024 * 1024;
+
+/// Download the page and get its title
+pub fn fetch_title(url: &str) -> Result<String> {
+    let agent = AgentBuilder::new()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent(concat!("bm/", env!("CARGO_PKG_VERSION")))
+        .build();
+
+    let response = agent.get(url).call().context("Could not fetch the page")?;
+    let mut page = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_PAGE_BYTES)
+        .read_to_end(&mut page)
+        .context("Could not read the page")?;
+
+    parse_title(String::from_utf8_lossy(&page).as_ref()).context("The page has no title")
+}
+
+/// The og:title meta tag is preferred since it usually leaves out the site name. Otherwise the `<title>`.
+fn parse_title(html: &str) -> Option<String> {
+    let og_title_re =
+        Regex::new(r#"(?is)<meta\s[^>]*property\s*=\s*["']og:title["'][^>]*>"#).unwrap();
+    let content_re = Regex::new(r#"(?is)content\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+    let title_re = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap();
+
+    let og_title = og_title_re
+        .find(html)
+        .and_then(|meta| content_re.captures(meta.as_str()))
+        .and_then(|content| content.get(1).or_else(|| content.get(2)))
+        .map(|content| content.as_str());
+    let title = title_re
+        .captures(html)
+        .and_then(|title| title.get(1))
+        .map(|title| title.as_str());
+
+    [og_title, title]
+        .into_iter()
+        .flatten()
+        .map(sanitize)
+        .find(|title| !title.is_empty())
+}
+
+/// Decode the title and keep it to a single line without the column separator so it is easy to read in the CSV
+fn sanitize(title: &str) -> String {
+    decode_entities(title)
+        .replace('|', "-")
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::page_title::parse_title;
+
+    #[test]
+    fn title() {
+        assert_eq!(
+            parse_title("<html><head>\n<TITLE lang=\"en\">\n  Rust | Docs &amp;\n  more\n</TITLE>"),
+            Some(String::from("Rust - Docs & more"))
+        );
+        assert_eq!(parse_title("<html><body>No title</body>"), None);
+        assert_eq!(parse_title("<title> </title>"), None);
+    }
+
+    #[test]
+    fn og_title_is_preferred() {
+        let html = r#"<head>
+            <title>The Rust Book | Rust</title>
+            <meta content='The Rust Book' property="og:title">
+        </head>"#;
+
+        assert_eq!(parse_title(html), Some(String::from("The Rust Book")));
+        // Falls back to the title if og:title is empty
+        assert_eq!(
+            parse_title(r#"<meta property="og:title" content=""><title>Title</title>"#),
+            Some(String::from("Title"))
+        );
+    }
+}
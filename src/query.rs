@@ -0,0 +1,410 @@
+//! Search queries. A query is a single REGEX run against the URL and description unless it contains a term scoped to a
+//! field, e.g. `desc:kubernetes domain:github.com tag:ops`. Then it is a list of white space separated terms:
+//!
+//! ```text
+//! url:REGEX       REGEX matches the URL
+//! desc:REGEX      REGEX matches the description (description: also works)
+//! tag:TAG         The bookmark has the tag (case insensitive)
+//! domain:DOMAIN   The URL's host is DOMAIN or a subdomain of it
+//! REGEX           REGEX matches the URL or description
+//! ```
+//!
+//! Terms next to each other must all match. OR between terms matches either side and binds looser than AND (which can
+//! also be written explicitly). Values containing spaces can be double quoted, e.g. `desc:"getting started"`.
+
+use anyhow::{Context, Result, bail};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::canonical_url::host_range;
+
+const FIELDS: [&str; 5] = ["url", "desc", "description", "tag", "domain"];
+
+pub enum Query {
+    /// The original syntax where the whole query is a REGEX
+    Regex(Regex),
+    /// Any of the groups of terms must match. Every term in a group must match
+    Fields(Vec<Vec<Term>>),
+}
+
+pub enum Term {
+    Any(Regex),
+    Url(Regex),
+    Description(Regex),
+    /// Lowercase tag
+    Tag(String),
+    /// Lowercase domain
+    Domain(String),
+}
+
+/// Byte ranges of the URL and description to highlight
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct QueryMatch {
+    pub url: Vec<Range<usize>>,
+    pub description: Vec<Range<usize>>,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self> {
+        // Only use the field syntax when it is asked for so existing REGEXes (including ones with spaces) still work
+        if !query
+            .split_whitespace()
+            .any(|word| field_prefix(word).is_some())
+        {
+            return Ok(Query::Regex(build_regex(query)?));
+        }
+
+        let tokens = tokenize(query)?;
+
+        let mut groups = vec![Vec::new()];
+        let mut expect_term = true;
+
+        for token in tokens {
+            match token.as_str() {
+                "AND" | "OR" if expect_term => bail!("{token} must be between two search terms"),
+                "AND" => expect_term = true,
+                "OR" => {
+                    groups.push(Vec::new());
+                    expect_term = true;
+                }
+                _ => {
+                    groups.last_mut().unwrap().push(parse_term(token.as_str())?);
+                    expect_term = false;
+                }
+            }
+        }
+
+        if expect_term {
+            bail!("The search query can't end with AND or OR");
+        }
+
+        Ok(Query::Fields(groups))
+    }
+
+    /// Where the query matched the bookmark, or None if it didn't match
+    pub fn find(&self, url: &str, description: &str, tags: &HashSet<String>) -> Option<QueryMatch> {
+        match self {
+            Query::Regex(re) => find_any(re, url, description),
+            Query::Fields(groups) => {
+                let mut matched = None;
+
+                // Keep going after the first group that matches so everything that matched is highlighted
+                for group in groups {
+                    let group_match =
+                        group
+                            .iter()
+                            .try_fold(QueryMatch::default(), |mut acc, term| {
+                                let term_match = term.find(url, description, tags)?;
+                                acc.url.extend(term_match.url);
+                                acc.description.extend(term_match.description);
+                                Some(acc)
+                            });
+
+                    if let Some(group_match) = group_match {
+                        let acc = matched.get_or_insert_with(QueryMatch::default);
+                        acc.url.extend(group_match.url);
+                        acc.description.extend(group_match.description);
+                    }
+                }
+
+                matched.map(|matched| QueryMatch {
+                    url: merge_ranges(matched.url),
+                    description: merge_ranges(matched.description),
+                })
+            }
+        }
+    }
+}
+
+impl Term {
+    fn find(&self, url: &str, description: &str, tags: &HashSet<String>) -> Option<QueryMatch> {
+        match self {
+            Term::Any(re) => find_any(re, url, description),
+            Term::Url(re) => find_ranges(re, url).map(|url| QueryMatch {
+                url,
+                description: Vec::new(),
+            }),
+            Term::Description(re) => find_ranges(re, description).map(|description| QueryMatch {
+                url: Vec::new(),
+                description,
+            }),
+            Term::Tag(tag) => tags.contains(tag).then(QueryMatch::default),
+            Term::Domain(domain) => {
+                let host = host_range(url)?;
+                let host_str = url[host.clone()].to_ascii_lowercase();
+                let is_match =
+                    host_str == *domain || host_str.ends_with(format!(".{domain}").as_str());
+
+                // Highlight the part of the host that matched the domain (i.e. not any subdomain)
+                is_match.then(|| {
+                    let matched = host.end - domain.len()..host.end;
+                    QueryMatch {
+                        url: vec![matched],
+                        description: Vec::new(),
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// The regex must match the URL or description (or both)
+fn find_any(re: &Regex, url: &str, description: &str) -> Option<QueryMatch> {
+    let url = find_ranges(re, url);
+    let description = find_ranges(re, description);
+
+    match (url, description) {
+        (None, None) => None,
+        (url, description) => Some(QueryMatch {
+            url: url.unwrap_or_default(),
+            description: description.unwrap_or_default(),
+        }),
+    }
+}
+
+fn find_ranges(re: &Regex, text: &str) -> Option<Vec<Range<usize>>> {
+    let ranges = re
+        .find_iter(text)
+        .map(|mat| mat.range())
+        .collect::<Vec<Range<usize>>>();
+
+    match ranges.is_empty() {
+        true => None,
+        false => Some(ranges),
+    }
+}
+
+/// Sort and combine overlapping ranges. Empty ranges are dropped since there is nothing to highlight.
+pub fn merge_ranges(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.retain(|range| !range.is_empty());
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+/// Case insensitive REGEX
+pub fn build_regex(regex: &str) -> Result<Regex> {
+    let re = RegexBuilder::new(regex)
+        .case_insensitive(true)
+        .build()
+        .context("Invalid REGEX")?;
+
+    Ok(re)
+}
+
+/// The field name if the token starts with a known field followed by a colon
+fn field_prefix(token: &str) -> Option<&'static str> {
+    let (field, _) = token.split_once(':')?;
+    FIELDS
+        .into_iter()
+        .find(|known| known.eq_ignore_ascii_case(field))
+}
+
+fn parse_term(token: &str) -> Result<Term> {
+    let Some(field) = field_prefix(token) else {
+        return Ok(Term::Any(build_regex(token)?));
+    };
+
+    let value = &token[field.len() + 1..];
+    if value.is_empty() {
+        bail!("Missing value after {field}: in the search query");
+    }
+
+    Ok(match field {
+        "url" => Term::Url(build_regex(value)?),
+        "desc" | "description" => Term::Description(build_regex(value)?),
+        "tag" => Term::Tag(value.to_lowercase()),
+        _ => Term::Domain(value.trim_end_matches('.').to_ascii_lowercase()),
+    })
+}
+
+/// Split on white space except inside double quotes. The quotes are removed.
+fn tokenize(query: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+
+    if quoted {
+        bail!("Unclosed quote in the search query");
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+// The expected highlights are often a single range
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use crate::query::{Query, QueryMatch};
+    use std::collections::HashSet;
+
+    fn find(query: &str, url: &str, description: &str, tags: &[&str]) -> Option<QueryMatch> {
+        let tags = tags
+            .iter()
+            .map(|tag| tag.to_lowercase())
+            .collect::<HashSet<String>>();
+        Query::parse(query).unwrap().find(url, description, &tags)
+    }
+
+    #[test]
+    fn regex_without_fields() {
+        let query = Query::parse("match me").unwrap();
+        assert!(matches!(query, Query::Regex(_)));
+        assert!(find("\"quoted", "https://a.com", "A \"quoted\" word", &[]).is_some());
+
+        // Colons are fine as long as they aren't after a field name
+        assert!(find("https://git", "https://github.com", "Code", &[]).is_some());
+    }
+
+    #[test]
+    fn fields_only_highlight_where_they_matched() {
+        let url = "https://github.com/kubernetes/kubernetes";
+        let description = "GitHub kubernetes";
+
+        assert_eq!(
+            find("desc:github", url, description, &[]),
+            Some(QueryMatch {
+                url: Vec::new(),
+                description: vec![0..6],
+            })
+        );
+        assert_eq!(
+            find("github", url, description, &[]),
+            Some(QueryMatch {
+                url: vec![8..14],
+                description: vec![0..6],
+            })
+        );
+    }
+
+    #[test]
+    fn terms_are_anded() {
+        let url = "https://github.com/kubernetes";
+        let description = "Kubernetes source";
+
+        assert!(
+            find(
+                "desc:kubernetes domain:github.com tag:ops",
+                url,
+                description,
+                &["Ops"]
+            )
+            .is_some()
+        );
+        assert!(
+            find(
+                "desc:kubernetes domain:github.com tag:ops",
+                url,
+                description,
+                &["dev"]
+            )
+            .is_none()
+        );
+        assert!(find("desc:kubernetes AND url:gitlab", url, description, &[]).is_none());
+    }
+
+    #[test]
+    fn or() {
+        let url = "https://go.dev";
+
+        assert!(find("tag:rust OR tag:go", url, "Go", &["Go"]).is_some());
+        assert!(find("tag:rust OR tag:go", url, "Go", &["python"]).is_none());
+        // AND binds tighter than OR
+        assert!(find("tag:rust desc:zzz OR tag:go", url, "Go", &["go"]).is_some());
+    }
+
+    #[test]
+    fn domain() {
+        assert_eq!(
+            find(
+                "domain:github.com",
+                "https://www.GitHub.com:443/x",
+                "X",
+                &[]
+            ),
+            Some(QueryMatch {
+                url: vec![12..22],
+                description: Vec::new(),
+            })
+        );
+        assert!(find("domain:hub.com", "https://github.com", "X", &[]).is_none());
+        assert!(
+            find(
+                "domain:github.com",
+                "https://example.com/github.com",
+                "X",
+                &[]
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn domain_longer_than_host() {
+        assert!(
+            find(
+                "domain:averyveryverylongdomainname.com",
+                "https://a.io",
+                "A",
+                &[]
+            )
+            .is_none()
+        );
+        assert!(find("domain:a.io", "http://[::1]/a.io", "A", &[]).is_none());
+    }
+
+    #[test]
+    fn quoted_values() {
+        assert!(
+            find(
+                "desc:\"getting started\"",
+                "https://a.com",
+                "Getting Started",
+                &[]
+            )
+            .is_some()
+        );
+        assert!(
+            find(
+                "desc:\"getting started\"",
+                "https://a.com",
+                "Getting going",
+                &[]
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Query::parse("desc:").is_err());
+        assert!(Query::parse("OR tag:a").is_err());
+        assert!(Query::parse("tag:a OR").is_err());
+        assert!(Query::parse("desc:\"a").is_err());
+        assert!(Query::parse("url:(").is_err());
+    }
+}
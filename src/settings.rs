@@ -0,0 +1,279 @@
+//! Per bookmarks file settings, read from a TOML file next to the CSV, e.g.
+//!
+//! ```toml
+//! [tags]
+//! case = "lowercase"
+//! aliases = { k8s = "kubernetes" }
+//!
+//! [urls]
+//! tracking-params = ["utm_*", "ref"]
+//! ```
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::csv::Line;
+
+/// Settings for a bookmarks file. They are kept next to it (and can be committed with it) so everyone sharing the
+/// file uses the same settings. Every setting is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub tags: TagPolicy,
+    pub urls: UrlPolicy,
+}
+
+/// How tags are normalized when bookmarks are written.
+/// Surrounding white space is always trimmed.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TagPolicy {
+    pub case: TagCase,
+    /// Replace each run of white space inside a tag with a dash. e.g. "machine learning" -> "machine-learning"
+    pub spaces_to_dashes: bool,
+    /// Tags which are replaced by another tag (matched case insensitively). e.g. k8s = "kubernetes"
+    pub aliases: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TagCase {
+    /// Keep the case the tag was typed with
+    #[default]
+    Preserve,
+    Lowercase,
+    /// Use the case of the first time the tag appears in the file
+    FirstSeen,
+}
+
+/// How URLs are canonicalized to find duplicate bookmarks
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct UrlPolicy {
+    /// Query parameters which are removed (case insensitive). A trailing * matches any parameter starting with the
+    /// rest, e.g. utm_*. Setting this replaces the default list.
+    pub tracking_params: Vec<String>,
+}
+
+impl Default for UrlPolicy {
+    fn default() -> Self {
+        let tracking_params = [
+            "utm_*", "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "mc_cid", "mc_eid",
+            "igshid", "yclid", "mkt_tok", "_ga", "_gl",
+        ];
+
+        Self {
+            tracking_params: tracking_params
+                .iter()
+                .map(|param| String::from(*param))
+                .collect(),
+        }
+    }
+}
+
+impl Settings {
+    /// Load the settings for the bookmarks file. The defaults are used if there isn't a settings file.
+    pub fn load(csv: &str) -> Result<Self> {
+        let path = settings_path(csv);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Settings::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Could not read {}", path.display()));
+            }
+        };
+
+        toml::from_str(&contents).with_context(|| format!("Invalid settings in {}", path.display()))
+    }
+}
+
+/// The settings file is the CSV path with the extension replaced by ".toml". e.g. bookmarks.csv -> bookmarks.toml
+pub fn settings_path(csv: &str) -> PathBuf {
+    PathBuf::from(csv).with_extension("toml")
+}
+
+/// Applies the tag policy. Remembers the tags it has seen so "first-seen" case is consistent.
+pub struct TagNormalizer<'a> {
+    policy: &'a TagPolicy,
+    /// Lowercase alias -> tag
+    aliases: HashMap<String, String>,
+    /// Lowercase tag -> first seen case
+    seen: HashMap<String, String>,
+}
+
+impl<'a> TagNormalizer<'a> {
+    pub fn new(policy: &'a TagPolicy) -> Self {
+        let aliases = policy
+            .aliases
+            .iter()
+            .map(|(alias, tag)| (clean(policy, alias).to_lowercase(), clean(policy, tag)))
+            .collect();
+
+        Self {
+            policy,
+            aliases,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Remember the case of the tags already in the file (in file order) for "first-seen"
+    pub fn with_existing<'b>(mut self, lines: impl IntoIterator<Item = &'b Line>) -> Self {
+        if self.policy.case == TagCase::FirstSeen {
+            for line in lines {
+                self.normalize(&line.tags);
+            }
+        }
+        self
+    }
+
+    /// Normalize the tags of a bookmark. Tags that end up the same (ignoring case) are only kept once and empty tags
+    /// are dropped.
+    pub fn normalize(&mut self, tags: &[String]) -> Vec<String> {
+        let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+
+        for tag in tags {
+            let tag = self.normalize_tag(tag);
+            if !tag.is_empty()
+                && !normalized
+                    .iter()
+                    .any(|existing| existing.to_lowercase() == tag.to_lowercase())
+            {
+                normalized.push(tag);
+            }
+        }
+
+        normalized
+    }
+
+    /// Normalize a tag the user asked for by name, e.g. the new tag of a rename. Its case is kept unless tags are
+    /// lowercased, since "first-seen" would turn a rename that only changes the case back into the old tag.
+    pub fn normalize_explicit(&self, tag: &str) -> String {
+        let tag = self.resolve(tag);
+        match self.policy.case {
+            TagCase::Lowercase => tag.to_lowercase(),
+            TagCase::Preserve | TagCase::FirstSeen => tag,
+        }
+    }
+
+    fn normalize_tag(&mut self, tag: &str) -> String {
+        let tag = self.resolve(tag);
+
+        match self.policy.case {
+            TagCase::Preserve => tag,
+            TagCase::Lowercase => tag.to_lowercase(),
+            TagCase::FirstSeen => self.seen.entry(tag.to_lowercase()).or_insert(tag).clone(),
+        }
+    }
+
+    /// Clean the tag and replace it if it is an alias
+    fn resolve(&self, tag: &str) -> String {
+        let tag = clean(self.policy, tag);
+        self.aliases
+            .get(&tag.to_lowercase())
+            .cloned()
+            .unwrap_or(tag)
+    }
+}
+
+/// Trim and, if the policy says so, replace white space with dashes
+fn clean(policy: &TagPolicy, tag: &str) -> String {
+    match policy.spaces_to_dashes {
+        true => tag.split_whitespace().collect::<Vec<&str>>().join("-"),
+        false => tag.trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv::Line;
+    use crate::settings::{Settings, TagCase, TagNormalizer, settings_path};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| String::from(*tag)).collect()
+    }
+
+    #[test]
+    fn load() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("bookmarks.csv");
+        let csv = csv.to_str().unwrap();
+
+        // No settings file
+        let settings = Settings::load(csv).unwrap();
+        assert_eq!(settings.tags.case, TagCase::Preserve);
+
+        fs::write(
+            settings_path(csv),
+            "[tags]\ncase = \"first-seen\"\nspaces-to-dashes = true\n\n[tags.aliases]\nk8s = \"kubernetes\"\n",
+        )
+        .unwrap();
+        let settings = Settings::load(csv).unwrap();
+        assert_eq!(settings.tags.case, TagCase::FirstSeen);
+        assert!(settings.tags.spaces_to_dashes);
+        assert_eq!(settings.tags.aliases["k8s"], "kubernetes");
+        assert!(
+            settings
+                .urls
+                .tracking_params
+                .contains(&String::from("utm_*"))
+        );
+
+        fs::write(settings_path(csv), "[urls]\ntracking-params = [\"ref\"]\n").unwrap();
+        let settings = Settings::load(csv).unwrap();
+        assert_eq!(settings.urls.tracking_params, vec!["ref"]);
+
+        fs::write(settings_path(csv), "[tags]\ncase = \"upper\"\n").unwrap();
+        let err = Settings::load(csv).err().unwrap();
+        assert!(format!("{err:#}").contains("unknown variant"), "{err:#}");
+    }
+
+    #[test]
+    fn default_policy_only_trims() {
+        let settings = Settings::default();
+        let mut normalizer = TagNormalizer::new(&settings.tags);
+
+        assert_eq!(
+            normalizer.normalize(&tags(&[" Machine Learning ", "K8s", "k8s", " "])),
+            tags(&["Machine Learning", "K8s"])
+        );
+    }
+
+    #[test]
+    fn aliases_dashes_and_lowercase() {
+        let mut settings = Settings::default();
+        settings.tags.case = TagCase::Lowercase;
+        settings.tags.spaces_to_dashes = true;
+        settings
+            .tags
+            .aliases
+            .insert(String::from("K8s"), String::from("Kubernetes"));
+        let mut normalizer = TagNormalizer::new(&settings.tags);
+
+        assert_eq!(
+            normalizer.normalize(&tags(&["k8s", "Machine  Learning", "kubernetes"])),
+            tags(&["kubernetes", "machine-learning"])
+        );
+    }
+
+    #[test]
+    fn first_seen_case() {
+        let mut settings = Settings::default();
+        settings.tags.case = TagCase::FirstSeen;
+        let existing = Line::new("https://www.rust-lang.org", "Rust", &["Rust"]);
+        let mut normalizer = TagNormalizer::new(&settings.tags).with_existing([&existing]);
+
+        assert_eq!(
+            normalizer.normalize(&tags(&["RUST", "go"])),
+            tags(&["Rust", "go"])
+        );
+        assert_eq!(normalizer.normalize(&tags(&["Go"])), tags(&["go"]));
+        // A tag asked for by name keeps its case
+        assert_eq!(normalizer.normalize_explicit(" RUST "), "RUST");
+    }
+}
@@ -0,0 +1,136 @@
+//! Tag suggestions for a new bookmark. Each existing bookmark votes for its tags: bookmarks on the same domain get a
+//! strong vote and bookmarks whose descriptions share words with the new one get a vote per shared word. The most voted
+//! tags (that the new bookmark doesn't already have) are suggested.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use crate::canonical_url::host_range;
+use crate::csv::Line;
+
+/// The most tags suggested at once
+const MAX_SUGGESTIONS: usize = 5;
+
+/// A bookmark on the same domain counts the same as this many shared description words
+const SAME_DOMAIN_WEIGHT: usize = 3;
+
+/// Words too common to say anything about what a bookmark is about
+const STOP_WORDS: [&str; 16] = [
+    "and", "are", "for", "from", "how", "into", "its", "not", "that", "the", "this", "what", "why",
+    "with", "you", "your",
+];
+
+/// Suggested tags from the existing bookmarks for a bookmark with the URL, description and tags, best first
+pub fn suggest_tags(lines: &[Line], url: &str, description: &str, tags: &[String]) -> Vec<String> {
+    let domain = domain(url);
+    let words = words(description);
+    let existing = tags
+        .iter()
+        .map(|tag| tag.to_lowercase())
+        .collect::<HashSet<String>>();
+
+    // Lowercase tag -> (first seen case, votes)
+    let mut votes: HashMap<String, (String, usize)> = HashMap::new();
+
+    for line in lines {
+        let same_domain = domain.is_some() && domain == self::domain(&line.url);
+        let shared_words = self::words(&line.description).intersection(&words).count();
+        let weight = shared_words + if same_domain { SAME_DOMAIN_WEIGHT } else { 0 };
+        if weight == 0 {
+            continue;
+        }
+
+        for tag in &line.tags {
+            if !existing.contains(&tag.to_lowercase()) {
+                votes
+                    .entry(tag.to_lowercase())
+                    .or_insert((tag.clone(), 0))
+                    .1 += weight;
+            }
+        }
+    }
+
+    let mut suggestions = votes.into_values().collect::<Vec<(String, usize)>>();
+    // Ties are alphabetical so the suggestions are stable
+    suggestions.sort_by_key(|(tag, votes)| (Reverse(*votes), tag.to_lowercase()));
+
+    suggestions
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(tag, _)| tag)
+        .collect()
+}
+
+/// Lowercase host without "www."
+fn domain(url: &str) -> Option<String> {
+    let host = url[host_range(url)?].to_ascii_lowercase();
+    match host.strip_prefix("www.") {
+        Some(host) => Some(host.to_string()),
+        None => Some(host),
+    }
+}
+
+/// Lowercase words of the description that say something about it
+fn words(description: &str) -> HashSet<String> {
+    description
+        .split(|c: char| !c.is_alphanumeric())
+        .map(|word| word.to_lowercase())
+        .filter(|word| word.chars().count() > 2 && !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv::Line;
+    use crate::suggest::{domain, suggest_tags};
+
+    #[test]
+    fn domains() {
+        assert_eq!(
+            domain("https://www.GitHub.com/a"),
+            Some(String::from("github.com"))
+        );
+        assert_eq!(
+            domain("https://docs.github.com"),
+            Some(String::from("docs.github.com"))
+        );
+        assert_eq!(domain("not a url"), None);
+    }
+
+    #[test]
+    fn suggestions() {
+        let lines = [
+            Line::new(
+                "https://github.com/rust-lang/rust",
+                "The Rust compiler",
+                &["Rust", "code"],
+            ),
+            Line::new(
+                "https://github.com/golang/go",
+                "The Go compiler",
+                &["go", "code"],
+            ),
+            Line::new("https://blog.rust-lang.org", "Rust blog", &["rust", "blog"]),
+            Line::new("https://example.com", "The example", &["misc"]),
+        ];
+
+        // Same domain (code) beats shared words (rust), which beat a shared stop word (misc isn't suggested at all)
+        assert_eq!(
+            suggest_tags(
+                &lines,
+                "https://www.github.com/tokio-rs/tokio",
+                "Rust async runtime for the web",
+                &[]
+            ),
+            vec!["code", "Rust", "go", "blog"]
+        );
+
+        // Tags the bookmark already has aren't suggested
+        assert_eq!(
+            suggest_tags(&lines, "https://a.com", "Rust", &[String::from("RUST")]),
+            vec!["blog", "code"]
+        );
+
+        assert!(suggest_tags(&lines, "https://a.com", "Nothing shared", &[]).is_empty());
+    }
+}
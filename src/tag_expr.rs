@@ -0,0 +1,307 @@
+//! Boolean expressions over tags, e.g. `(rust | go) & !internal`
+//!
+//! Grammar (lowest precedence first):
+//!
+//! ```text
+//! or      = and ("|" and)*
+//! and     = not ("&" not)*
+//! not     = "!" not | primary
+//! primary = "(" or ")" | TAG
+//! ```
+//!
+//! A TAG is either double quoted (so it can contain any character except ") or the text up to the next operator,
+//! parenthesis or quote with the surrounding white space trimmed. This allows tags with spaces without quoting.
+
+use anyhow::{Result, bail};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagExpr {
+    /// Tag which must be present, as a lookup key
+    Tag(String),
+    Not(Box<TagExpr>),
+    And(Vec<TagExpr>),
+    Or(Vec<TagExpr>),
+}
+
+impl TagExpr {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let tokens = match tokenize(expr) {
+            Ok(tokens) => tokens,
+            Err((msg, pos)) => bail!(error_message(expr, msg.as_str(), pos)),
+        };
+
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            end: expr.chars().count(),
+        };
+
+        let parsed = parser.parse_or().and_then(|parsed| match parser.peek() {
+            None => Ok(parsed),
+            Some((token, pos)) => Err((format!("Unexpected {}", token.describe()), *pos)),
+        });
+
+        match parsed {
+            Ok(parsed) => Ok(parsed),
+            Err((msg, pos)) => bail!(error_message(expr, msg.as_str(), pos)),
+        }
+    }
+
+    /// Evaluate against the lowercase tags of a bookmark
+    pub fn eval(&self, tags: &HashSet<String>) -> bool {
+        match self {
+            TagExpr::Tag(tag) => tags.contains(tag),
+            TagExpr::Not(expr) => !expr.eval(tags),
+            TagExpr::And(exprs) => exprs.iter().all(|expr| expr.eval(tags)),
+            TagExpr::Or(exprs) => exprs.iter().any(|expr| expr.eval(tags)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Tag(String),
+    Or,
+    And,
+    Not,
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Tag(tag) => format!("tag \"{tag}\""),
+            Token::Or => String::from("'|'"),
+            Token::And => String::from("'&'"),
+            Token::Not => String::from("'!'"),
+            Token::Open => String::from("'('"),
+            Token::Close => String::from("')'"),
+        }
+    }
+}
+
+/// How a tag that is asked for is compared to a bookmark's (lowercase) tags. Tags are case insensitive and a trailing /
+/// is ignored, since it only says the tag is a level of the hierarchy. e.g. Lang/ -> lang
+pub fn lookup_key(tag: &str) -> String {
+    tag.trim_end_matches('/').to_lowercase()
+}
+
+/// An error message and the character position it applies to
+type ParseError = (String, usize);
+
+/// Split the expression into tokens along with their character position
+fn tokenize(expr: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars = expr.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let token = match chars[pos] {
+            c if c.is_whitespace() => {
+                pos += 1;
+                continue;
+            }
+            '|' => Token::Or,
+            '&' => Token::And,
+            '!' => Token::Not,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '"' => {
+                let start = pos;
+                let len = chars[start + 1..]
+                    .iter()
+                    .position(|c| *c == '"')
+                    .ok_or((String::from("Unclosed quote"), start))?;
+                let tag = chars[start + 1..start + 1 + len].iter().collect::<String>();
+                if tag.trim().is_empty() {
+                    return Err((String::from("Empty tag"), start));
+                }
+                tokens.push((Token::Tag(lookup_key(tag.trim())), start));
+                pos = start + len + 2;
+                continue;
+            }
+            _ => {
+                let start = pos;
+                while pos < chars.len() && !"|&!()\"".contains(chars[pos]) {
+                    pos += 1;
+                }
+                let tag = chars[start..pos].iter().collect::<String>();
+                tokens.push((Token::Tag(lookup_key(tag.trim())), start));
+                continue;
+            }
+        };
+
+        tokens.push((token, pos));
+        pos += 1;
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    /// Character position of the end of the expression for errors about missing tokens
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(Token, usize)> {
+        self.tokens.get(self.next)
+    }
+
+    /// Consume the next token if it is the expected one
+    fn accept(&mut self, expected: Token) -> bool {
+        match self.peek() {
+            Some((token, _)) if *token == expected => {
+                self.next += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<TagExpr, ParseError> {
+        let mut exprs = vec![self.parse_and()?];
+        while self.accept(Token::Or) {
+            exprs.push(self.parse_and()?);
+        }
+
+        Ok(match exprs.len() {
+            1 => exprs.remove(0),
+            _ => TagExpr::Or(exprs),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<TagExpr, ParseError> {
+        let mut exprs = vec![self.parse_not()?];
+        while self.accept(Token::And) {
+            exprs.push(self.parse_not()?);
+        }
+
+        Ok(match exprs.len() {
+            1 => exprs.remove(0),
+            _ => TagExpr::And(exprs),
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<TagExpr, ParseError> {
+        match self.accept(Token::Not) {
+            true => Ok(TagExpr::Not(Box::new(self.parse_not()?))),
+            false => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<TagExpr, ParseError> {
+        let (token, pos) = match self.tokens.get(self.next) {
+            Some((token, pos)) => (token, *pos),
+            None => return Err((String::from("Expected a tag"), self.end)),
+        };
+
+        match token {
+            Token::Tag(tag) => {
+                let tag = tag.clone();
+                self.next += 1;
+                Ok(TagExpr::Tag(tag))
+            }
+            Token::Open => {
+                self.next += 1;
+                let expr = self.parse_or()?;
+                if !self.accept(Token::Close) {
+                    return Err((String::from("Unclosed '('"), pos));
+                }
+                Ok(expr)
+            }
+            token => Err((format!("Expected a tag, not {}", token.describe()), pos)),
+        }
+    }
+}
+
+/// Point at the problem in the expression
+fn error_message(expr: &str, msg: &str, pos: usize) -> String {
+    format!(
+        "Invalid tag expression: {msg} at character {}\n  {expr}\n  {}^",
+        pos + 1,
+        " ".repeat(pos)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tag_expr::TagExpr;
+    use std::collections::HashSet;
+
+    fn tags(tags: &[&str]) -> HashSet<String> {
+        tags.iter().map(|tag| tag.to_lowercase()).collect()
+    }
+
+    fn eval(expr: &str, bookmark_tags: &[&str]) -> bool {
+        TagExpr::parse(expr).unwrap().eval(&tags(bookmark_tags))
+    }
+
+    #[test]
+    fn or_and_not() {
+        assert!(eval("rust | go", &["Go"]));
+        assert!(!eval("rust | go", &["Python"]));
+        assert!(eval("docs & !internal", &["Docs"]));
+        assert!(!eval("docs & !internal", &["docs", "Internal"]));
+        assert!(eval("!!docs", &["docs"]));
+    }
+
+    #[test]
+    fn precedence_and_grouping() {
+        // & binds tighter than |
+        assert!(eval("a | b & c", &["a"]));
+        assert!(!eval("(a | b) & c", &["a"]));
+        assert!(eval("(a | b) & c", &["b", "c"]));
+        assert!(!eval("!(a | b)", &["b"]));
+    }
+
+    #[test]
+    fn tags_with_spaces() {
+        assert_eq!(
+            TagExpr::parse(" Tag1 a Doodle Do |\"x & y\"").unwrap(),
+            TagExpr::Or(vec![
+                TagExpr::Tag(String::from("tag1 a doodle do")),
+                TagExpr::Tag(String::from("x & y")),
+            ])
+        );
+    }
+
+    #[test]
+    fn hierarchy_levels() {
+        assert_eq!(
+            TagExpr::parse("Lang/ | \"Go/\"").unwrap(),
+            TagExpr::Or(vec![
+                TagExpr::Tag(String::from("lang")),
+                TagExpr::Tag(String::from("go")),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = TagExpr::parse("rust |").err().unwrap().to_string();
+        assert_eq!(
+            err,
+            "Invalid tag expression: Expected a tag at character 7\n  rust |\n        ^"
+        );
+
+        let err = TagExpr::parse("(rust | go").err().unwrap().to_string();
+        assert!(err.contains("Unclosed '(' at character 1"), "{err}");
+
+        let err = TagExpr::parse("rust go)").err().unwrap().to_string();
+        assert!(err.contains("Unexpected ')' at character 8"), "{err}");
+
+        let err = TagExpr::parse("& rust").err().unwrap().to_string();
+        assert!(
+            err.contains("Expected a tag, not '&' at character 1"),
+            "{err}"
+        );
+
+        let err = TagExpr::parse("\"rust").err().unwrap().to_string();
+        assert!(err.contains("Unclosed quote at character 1"), "{err}");
+    }
+}
@@ -7,16 +7,19 @@ use std::fs;
 // Add methods on commands
 use predicates::prelude::*;
 // Used for writing assertions
-use std::process::Command;
+use std::process::{Command, Stdio};
 // Run programs
 use anyhow::{Result, ensure};
 use git2::Repository;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 use tempfile::{TempDir, tempdir};
 
-const HEADER_ROW: &str = "URL|DESCRIPTION|TAGS";
+const HEADER_ROW: &str = "URL|DESCRIPTION|TAGS|#escaped";
 
 #[test]
 fn invalid_command() -> Result<()> {
@@ -70,11 +73,114 @@ fn no_duplicate_urls() -> Result<()> {
 }
 
 #[test]
-fn validate_fails_for_pipe_in_description() -> Result<()> {
-    let (_csv_dir, _csv_path, mut cmd) = setup()?;
+fn no_duplicate_canonical_urls() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(&csv_path, "https://example.com/", "Example", None)?;
+
+    for url in [
+        "http://example.com",
+        "https://EXAMPLE.com:443",
+        "https://example.com/?utm_source=x",
+    ] {
+        setup_cmd(&csv_path)?
+            .args(["a", url, "Example"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(format!(
+                "{url} has already been bookmarked as https://example.com/"
+            )));
+    }
 
-    cmd.arg("a").arg("https://google.com").arg("Goo|gle");
-    cmd.assert().failure();
+    // The canonical form can be stored instead
+    setup_cmd(&csv_path)?
+        .args([
+            "a",
+            "https://Example.com/docs/?utm_medium=y&b=2&a=1",
+            "Docs",
+        ])
+        .arg("--canonical")
+        .assert()
+        .success();
+
+    setup_cmd(&csv_path)?
+        .args(["s", "Docs", "--format", "tsv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "https://example.com/docs?a=1&b=2\t",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn separators_are_escaped() -> Result<()> {
+    let (_csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://google.com/search?q=a|b",
+        "Goo|gle",
+        Some(vec!["Search,Engine"]),
+    )?;
+
+    let contents = fs::read_to_string(&csv_path)?;
+    assert_eq!(
+        contents,
+        format!("{HEADER_ROW}\nhttps://google.com/search?q=a\\|b|Goo\\|gle|Search\\,Engine\n")
+    );
+
+    cmd.arg("search").arg("-t").arg("Search,Engine");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Goo|gle"))
+        .stdout(predicate::str::contains("https://google.com/search?q=a|b"));
+
+    Ok(())
+}
+
+#[test]
+fn legacy_files_are_not_unescaped() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+    fs::write(
+        &csv_path,
+        "URL|DESCRIPTION|TAGS\nhttps://a.com|Windows dir C:\\|win\nhttps://b.com|regex \\n newline|\n",
+    )?;
+
+    setup_cmd(&csv_path)?
+        .args(["search", "-t", "win"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Windows dir C:\\"))
+        .stderr(predicate::str::is_empty());
+    setup_cmd(&csv_path)?
+        .args(["search", "--format", "tsv", "regex"])
+        .assert()
+        .success()
+        .stdout("https://b.com\tregex \\\\n newline\t\n");
+
+    // Values that don't need escaping are added as they are
+    setup_cmd(&csv_path)?
+        .args(["add", "https://c.com", "C", "--no-commit"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&csv_path)?,
+        "URL|DESCRIPTION|TAGS\nhttps://a.com|Windows dir C:\\|win\nhttps://b.com|regex \\n newline|\nhttps://c.com|C|\n"
+    );
+
+    // Otherwise the file is upgraded to escape values
+    setup_cmd(&csv_path)?
+        .args(["add", "https://d.com", "D|E", "--no-commit"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&csv_path)?,
+        format!(
+            "{HEADER_ROW}\nhttps://a.com|Windows dir C:\\\\|win\nhttps://b.com|regex \\\\n newline|\nhttps://c.com|C|\nhttps://d.com|D\\|E|\n"
+        )
+    );
 
     Ok(())
 }
@@ -331,6 +437,523 @@ fn multi_tag_query() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn tag_expression_query() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://rust-lang.org",
+        "Rust",
+        Some(vec!["Rust", "Docs"]),
+    )?;
+    setup_add(&csv_path, "https://go.dev", "Go", Some(vec!["go", "docs"]))?;
+    setup_add(
+        &csv_path,
+        "https://wiki.example.com",
+        "Wiki",
+        Some(vec!["docs", "internal"]),
+    )?;
+
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.arg("s").arg("-w").arg("rust | go");
+    test_count_matches(&mut cmd, 2)?;
+
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.arg("s").arg("--where").arg("docs & !internal");
+    test_count_matches(&mut cmd, 2)?;
+
+    // Combined with a REGEX and tags
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.args(["s", "o", "-t", "docs", "-w", "!(rust | internal)"]);
+    test_count_matches(&mut cmd, 1)?;
+
+    setup_cmd(&csv_path)?
+        .args(["s", "-w", "(rust | go"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Invalid tag expression: Unclosed '(' at character 1",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn field_qualified_query() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://github.com/kubernetes/kubernetes",
+        "Kubernetes source",
+        Some(vec!["ops"]),
+    )?;
+    setup_add(
+        &csv_path,
+        "https://docs.example.com/github",
+        "Using GitHub actions",
+        Some(vec!["ci"]),
+    )?;
+    setup_add(&csv_path, "https://kubernetes.io", "Kubernetes", None)?;
+
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.args(["s", "desc:github"]);
+    test_count_matches(&mut cmd, 1)?;
+
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.args(["s", "desc:kubernetes domain:github.com tag:ops"]);
+    test_count_matches(&mut cmd, 1)?;
+
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.args(["s", "domain:kubernetes.io OR tag:ci"]);
+    test_count_matches(&mut cmd, 2)?;
+
+    // Without a field, the whole query is still one REGEX against the URL and description
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.args(["s", "github"]);
+    test_count_matches(&mut cmd, 2)?;
+
+    Ok(())
+}
+
+#[test]
+fn fuzzy_search_ranked_and_limited() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(&csv_path, "https://example.com/k8s", "Kubernets tips", None)?;
+    setup_add(&csv_path, "https://kubernetes.io", "Kubernetes", None)?;
+    setup_add(&csv_path, "https://go.dev", "Go", None)?;
+
+    // The typo in the first description means only the second matches as a REGEX
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.args(["s", "kubernetes"]);
+    test_count_matches(&mut cmd, 1)?;
+
+    // The exact match is ranked first
+    setup_cmd(&csv_path)?
+        .args(["s", "kubernetes", "--fuzzy", "--format", "tsv"])
+        .assert()
+        .success()
+        .stdout("https://kubernetes.io\tKubernetes\t\nhttps://example.com/k8s\tKubernets tips\t\n");
+
+    setup_cmd(&csv_path)?
+        .args([
+            "s",
+            "kuberentes",
+            "--fuzzy",
+            "--limit",
+            "1",
+            "--format",
+            "tsv",
+        ])
+        .assert()
+        .success()
+        .stdout("https://kubernetes.io\tKubernetes\t\n");
+
+    Ok(())
+}
+
+#[test]
+fn sort_and_page_search_results() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(&csv_path, "https://c.com", "Charlie", Some(vec!["t"]))?;
+    setup_add(&csv_path, "https://a.com", "Alpha", Some(vec!["t"]))?;
+    setup_add(&csv_path, "https://b.com", "Bravo", Some(vec!["t"]))?;
+
+    setup_cmd(&csv_path)?
+        .args(["s", "-t", "t", "--sort", "url", "--format", "tsv"])
+        .assert()
+        .success()
+        .stdout("https://a.com\tAlpha\tt\nhttps://b.com\tBravo\tt\nhttps://c.com\tCharlie\tt\n");
+
+    setup_cmd(&csv_path)?
+        .args(["s", "-t", "t", "--sort", "description", "--reverse"])
+        .args(["--offset", "1", "--limit", "1", "--format", "tsv"])
+        .assert()
+        .success()
+        .stdout("https://b.com\tBravo\tt\n");
+
+    // Without --sort, --reverse shows the most recently added lines of the file first
+    setup_cmd(&csv_path)?
+        .args([
+            "s",
+            "-t",
+            "t",
+            "--reverse",
+            "--limit",
+            "1",
+            "--format",
+            "tsv",
+        ])
+        .assert()
+        .success()
+        .stdout("https://b.com\tBravo\tt\n");
+
+    Ok(())
+}
+
+#[test]
+fn suggest_tags_when_adding() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://github.com/rust-lang/rust",
+        "Rust compiler",
+        Some(vec!["rust", "code"]),
+    )?;
+    setup_add(
+        &csv_path,
+        "https://example.com",
+        "Example",
+        Some(vec!["misc"]),
+    )?;
+
+    setup_cmd(&csv_path)?
+        .args([
+            "add",
+            "https://github.com/golang/go",
+            "Go compiler",
+            "-t",
+            "go",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Suggested tags: code, rust (--auto-tag to add them)",
+        ));
+
+    setup_cmd(&csv_path)?
+        .args(["add", "https://github.com/tokio-rs/tokio", "Rust runtime"])
+        .arg("--auto-tag")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Tagged with suggested tags: code, rust, go",
+        ));
+
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.args(["s", "-t", "code", "-t", "rust", "tokio"]);
+    test_count_matches(&mut cmd, 1)?;
+
+    Ok(())
+}
+
+#[test]
+fn suggestions_use_normalized_tags() -> Result<()> {
+    let (csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://kubernetes.io",
+        "Kubernetes docs",
+        Some(vec!["kubernetes"]),
+    )?;
+
+    fs::write(
+        csv_dir.path().join("tmp.toml"),
+        "[tags.aliases]\nk8s = \"kubernetes\"\n",
+    )?;
+
+    // k8s is the kubernetes tag, so it isn't suggested again
+    setup_cmd(&csv_path)?
+        .args(["add", "https://helm.sh", "Kubernetes package manager"])
+        .args(["-t", "k8s", "--no-commit"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Suggested tags").not());
+
+    Ok(())
+}
+
+#[test]
+fn dedupe() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    // Written directly since add won't add duplicates
+    let duplicates = format!(
+        "{HEADER_ROW}\nhttps://example.com/|Example|web\nhttps://go.dev|Go|go\nhttp://EXAMPLE.com?utm_source=x|Example site|Docs,web\nhttps://go.dev/|The Go language|lang\n"
+    );
+    fs::write(&csv_path, &duplicates)?;
+
+    // Pick the second description of the first group and skip the second group
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.args(["dedupe", "--no-commit"]);
+    assert_cmd::Command::from_std(cmd)
+        .write_stdin("2\nn\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 duplicate bookmark(s) merged"));
+    assert_eq!(
+        fs::read_to_string(&csv_path)?,
+        format!(
+            "{HEADER_ROW}\nhttps://example.com/|Example site|web,Docs\nhttps://go.dev|Go|go\nhttps://go.dev/|The Go language|lang\n"
+        )
+    );
+
+    setup_cmd(&csv_path)?
+        .args(["dedupe", "--auto", "--description", "first", "--no-commit"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&csv_path)?,
+        format!(
+            "{HEADER_ROW}\nhttps://example.com/|Example site|web,Docs\nhttps://go.dev|Go|go,lang\n"
+        )
+    );
+
+    setup_cmd(&csv_path)?
+        .arg("dedupe")
+        .arg("--no-commit")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No duplicate bookmarks"));
+
+    Ok(())
+}
+
+#[test]
+fn check_bookmarks_file() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(&csv_path, "https://a.com", "A", Some(vec!["x"]))?;
+    setup_cmd(&csv_path)?
+        .arg("check")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No problems found"));
+
+    fs::write(
+        &csv_path,
+        format!("{HEADER_ROW}\nhttps://a.com |A|x,\nhttps://b.com|B|y|z\nhttps://a.com|A|x\n"),
+    )?;
+
+    setup_cmd(&csv_path)?
+        .arg("fsck")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Line 2: Leading or trailing white space (fixable with --fix)",
+        ))
+        .stdout(predicate::str::contains(
+            "Line 2: Empty tag (e.g. from a trailing comma) (fixable with --fix)",
+        ))
+        .stdout(predicate::str::contains(
+            "Line 3: CSV line has 4 columns instead of 3",
+        ))
+        .stdout(predicate::str::contains("Line 4: Duplicate of line 2"))
+        .stderr(predicate::str::contains("4 problem(s) found"));
+
+    // The line with too many columns still needs fixing by hand
+    setup_cmd(&csv_path)?
+        .args(["check", "--fix", "--no-commit"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("3 problem(s) fixed"))
+        .stderr(predicate::str::contains("1 problem(s) found"));
+    assert_eq!(
+        fs::read_to_string(&csv_path)?,
+        format!("{HEADER_ROW}\nhttps://a.com|A|x\nhttps://b.com|B|y|z\n")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn skip_malformed_lines() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    fs::write(
+        &csv_path,
+        format!(
+            "{HEADER_ROW}\nhttps://a.com|A|x\nhttps://b.com|B|x|bad merge\nhttps://c.com|C|x\n"
+        ),
+    )?;
+
+    // Read-only commands skip the line with a warning
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.args(["s", "-t", "x"]);
+    test_count_matches(&mut cmd, 2)?;
+    setup_cmd(&csv_path)?
+        .args(["tags", "--machine"])
+        .assert()
+        .success()
+        .stdout("x\n")
+        .stderr(predicate::str::contains(
+            "Skipping line 3 of the CSV: CSV line has 4 columns instead of 3",
+        ));
+
+    // Unless they are strict
+    setup_cmd(&csv_path)?
+        .args(["s", "-t", "x", "--strict"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Line 3 of the CSV is invalid"));
+    setup_cmd(&csv_path)?
+        .env("BOOKMARK_MANAGER_STRICT", "true")
+        .args(["tags"])
+        .assert()
+        .failure();
+
+    // Commands that change the file always fail so the line isn't lost
+    setup_cmd(&csv_path)?
+        .args(["rm", "https://a.com", "--yes", "--no-commit"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Line 3 of the CSV is invalid"));
+
+    Ok(())
+}
+
+#[test]
+fn linkcheck() -> Result<()> {
+    let (csv_dir, csv_path, _cmd) = setup()?;
+    let server = serve_pages()?;
+
+    // Nothing can listen on port 0
+    let refused = "http://127.0.0.1:0/";
+
+    for path in [
+        "/ok",
+        "/moved",
+        "/temporary",
+        "/gone",
+        "/get-only",
+        "/duplicate",
+        "/forbidden",
+        "/no-head",
+    ] {
+        setup_add(
+            &csv_path,
+            format!("{server}{path}").as_str(),
+            path,
+            Some(vec!["web"]),
+        )?;
+    }
+    setup_add(&csv_path, refused, "Refused", None)?;
+
+    setup_cmd(&csv_path)?
+        .args(["linkcheck", "-t", "web", "--timeout", "5"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("200 {server}/ok\n")))
+        .stdout(predicate::str::contains(format!(
+            "301 {server}/moved -> {server}/new 200 (moved permanently)"
+        )))
+        .stdout(predicate::str::contains(format!(
+            "302 {server}/temporary -> {server}/ok 200"
+        )))
+        .stdout(predicate::str::contains(format!("404 {server}/gone")))
+        .stdout(predicate::str::contains(format!("200 {server}/get-only")))
+        .stdout(predicate::str::contains(format!("403 {server}/forbidden")))
+        .stdout(predicate::str::contains(format!("200 {server}/no-head")))
+        .stdout(predicate::str::contains(
+            "3 ok, 3 redirected, 1 failed, 1 dead",
+        ));
+
+    setup_cmd(&csv_path)?
+        .args(["linkcheck", "--tag-dead", "--update-redirects", "-j", "2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ERROR (").and(predicate::str::contains(refused)))
+        .stdout(predicate::str::contains(format!(
+            "Not updating {server}/duplicate since {server}/ok is already bookmarked"
+        )))
+        .stdout(predicate::str::contains(
+            "2 bookmark(s) tagged dead and 1 URL(s) updated and committed to git",
+        ));
+
+    assert_eq!(
+        fs::read_to_string(&csv_path)?,
+        format!(
+            "{HEADER_ROW}\n{server}/ok|/ok|web\n{server}/new|/moved|web\n{server}/temporary|/temporary|web\n{server}/gone|/gone|web,dead\n{server}/get-only|/get-only|web\n{server}/duplicate|/duplicate|web\n{server}/forbidden|/forbidden|web\n{server}/no-head|/no-head|web\n{refused}|Refused|dead\n"
+        )
+    );
+    assert_eq!(
+        last_commit_message(csv_dir.path())?,
+        "Check links\n\n2 bookmark(s) tagged dead and 1 URL(s) updated"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn linkcheck_offline() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(&csv_path, "http://127.0.0.1:0/a", "A", None)?;
+    setup_add(&csv_path, "http://127.0.0.1:0/b", "B", None)?;
+    let before = fs::read_to_string(&csv_path)?;
+
+    // Nothing could be reached, which is what being offline looks like
+    setup_cmd(&csv_path)?
+        .args(["linkcheck", "--tag-dead"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "0 ok, 0 redirected, 0 failed, 2 dead",
+        ))
+        .stdout(predicate::str::contains("none are tagged dead"))
+        .stdout(predicate::str::contains("No bookmarks changed"));
+
+    assert_eq!(fs::read_to_string(&csv_path)?, before);
+
+    Ok(())
+}
+
+#[test]
+fn fetch_page_title() -> Result<()> {
+    let (csv_dir, csv_path, _cmd) = setup()?;
+    let server = serve_pages()?;
+
+    // The title is used when there is no description
+    setup_cmd(&csv_path)?
+        .args(["add", format!("{server}/titled").as_str()])
+        .assert()
+        .success();
+    assert_eq!(
+        last_commit_message(csv_dir.path())?,
+        format!("Add bookmark for {server}/titled - Rust - Docs & more")
+    );
+
+    // Falls back to the host when the page can't be fetched (nothing can listen on port 0)
+    setup_cmd(&csv_path)?
+        .args(["add", "http://localhost:0/a"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "using localhost as the description",
+        ));
+
+    // Or the description if there is one
+    setup_cmd(&csv_path)?
+        .args(["add", "--fetch-title", "http://localhost:0/b", "B"])
+        .assert()
+        .success();
+
+    // The title is preferred with --fetch-title
+    setup_cmd(&csv_path)?
+        .args([
+            "add",
+            "--fetch-title",
+            format!("{server}/titled?b").as_str(),
+            "Docs",
+        ])
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&csv_path)?,
+        format!(
+            "{HEADER_ROW}\n{server}/titled|Rust - Docs & more|\nhttp://localhost:0/a|localhost|\nhttp://localhost:0/b|B|\n{server}/titled?b|Rust - Docs & more|\n"
+        )
+    );
+
+    Ok(())
+}
+
 #[test]
 fn list_tags() -> Result<()> {
     let (_csv_dir, csv_path, mut cmd) = setup()?;
@@ -423,7 +1046,186 @@ search
 }
 
 #[test]
-fn csv_not_in_git_root() -> Result<()> {
+fn rename_and_merge_tags() -> Result<()> {
+    let (csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://rust-lang.org",
+        "Rust",
+        Some(vec!["RUST"]),
+    )?;
+    setup_add(
+        &csv_path,
+        "https://crates.io",
+        "Crates",
+        Some(vec!["rust", "Crates"]),
+    )?;
+    setup_add(
+        &csv_path,
+        "https://go.dev",
+        "Go",
+        Some(vec!["golang", "go-lang"]),
+    )?;
+
+    setup_cmd(&csv_path)?
+        .args(["tags", "rename", "rust", "Rust", "-i"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "2 bookmark(s) changed and committed to git",
+        ));
+    assert_eq!(
+        last_commit_message(csv_dir.path())?,
+        "Rename tag rust to Rust\n\n2 bookmark(s) changed"
+    );
+
+    setup_cmd(&csv_path)?
+        .args(["tags", "merge", "golang", "go-lang", "--into", "go"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 bookmark(s) changed"));
+
+    let contents = fs::read_to_string(&csv_path)?;
+    assert_eq!(
+        contents,
+        format!(
+            "{HEADER_ROW}\nhttps://rust-lang.org|Rust|Rust\nhttps://crates.io|Crates|Rust,Crates\nhttps://go.dev|Go|go\n"
+        )
+    );
+
+    setup_cmd(&csv_path)?
+        .args(["tags", "rename", "missing", "other"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No bookmarks changed"));
+
+    Ok(())
+}
+
+#[test]
+fn tag_policy() -> Result<()> {
+    let (csv_dir, csv_path, _cmd) = setup()?;
+
+    // Added before there was a policy
+    setup_add(
+        &csv_path,
+        "https://kubernetes.io",
+        "Kubernetes",
+        Some(vec!["K8s", "Dev  Ops"]),
+    )?;
+
+    fs::write(
+        csv_dir.path().join("tmp.toml"),
+        "[tags]\ncase = \"lowercase\"\nspaces-to-dashes = true\n\n[tags.aliases]\nk8s = \"kubernetes\"\n",
+    )?;
+
+    // The settings file isn't committed, so don't commit
+    setup_cmd(&csv_path)?
+        .args([
+            "add",
+            "https://helm.sh",
+            "Helm",
+            "-t",
+            " k8s ",
+            "-t",
+            "Package Manager",
+        ])
+        .arg("--no-commit")
+        .assert()
+        .success();
+
+    setup_cmd(&csv_path)?
+        .args(["tags", "normalize", "--no-commit"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 bookmark(s) changed"));
+
+    let contents = fs::read_to_string(&csv_path)?;
+    assert_eq!(
+        contents,
+        format!(
+            "{HEADER_ROW}\nhttps://kubernetes.io|Kubernetes|kubernetes,dev-ops\nhttps://helm.sh|Helm|kubernetes,package-manager\n"
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn tag_counts() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(&csv_path, "https://a.com", "A", Some(vec!["Rust", "go"]))?;
+    setup_add(&csv_path, "https://b.com", "B", Some(vec!["rust"]))?;
+    setup_add(&csv_path, "https://c.com", "C", Some(vec!["Rust", "zig"]))?;
+
+    setup_cmd(&csv_path)?
+        .args(["tags", "--count", "--sort", "count"])
+        .assert()
+        .success()
+        .stdout("3  Rust, rust\n1  go\n1  zig\n");
+
+    setup_cmd(&csv_path)?
+        .args(["tags", "--count", "--unused-below", "2", "--machine"])
+        .assert()
+        .success()
+        .stdout("1\tgo\n1\tzig\n");
+
+    setup_cmd(&csv_path)?
+        .args(["tags", "--json", "--unused-below", "2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "\"name\": \"go\",\n    \"variants\": [\n      \"go\"\n    ],\n    \"count\": 1",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn hierarchical_tags() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://tokio.rs",
+        "Tokio",
+        Some(vec!["lang/rust/async", "lang/rust"]),
+    )?;
+    setup_add(
+        &csv_path,
+        "https://rust-lang.org",
+        "Rust",
+        Some(vec!["Lang/Rust"]),
+    )?;
+    setup_add(&csv_path, "https://go.dev", "Go", Some(vec!["lang/go"]))?;
+    setup_add(
+        &csv_path,
+        "https://example.com",
+        "Example",
+        Some(vec!["misc"]),
+    )?;
+
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.args(["s", "-t", "lang"]);
+    test_count_matches(&mut cmd, 3)?;
+
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.args(["s", "-t", "lang/rust"]);
+    test_count_matches(&mut cmd, 2)?;
+
+    setup_cmd(&csv_path)?
+        .args(["tags", "--tree"])
+        .assert()
+        .success()
+        .stdout("lang (3)\n  go (1)\n  rust (2)\n    async (1)\nmisc (1)\n");
+
+    Ok(())
+}
+
+#[test]
+fn csv_not_in_git_root() -> Result<()> {
     // Most of this code is from setup()
     let dir = tempdir()?;
     let sub_dir = dir.path().join("sub_dir");
@@ -509,6 +1311,645 @@ fn no_commit() -> Result<()> {
     Ok(())
 }
 
+#[test]
+/// This test also tests the edit alias 'e'
+fn edit_bookmark() -> Result<()> {
+    let (csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://google.com",
+        "Gogle Search Engine",
+        Some(vec!["Search", "Engine"]),
+    )?;
+
+    cmd.arg("e")
+        .arg("https://google.com")
+        .arg("--url")
+        .arg("https://www.google.com")
+        .arg("-d")
+        .arg("Google Search Engine")
+        .arg("-t")
+        .arg("Google")
+        .arg("-r")
+        .arg("engine");
+    cmd.assert().success().stdout(predicate::str::contains(
+        "Bookmark edited and committed to git",
+    ));
+
+    let contents = fs::read_to_string(&csv_path)?;
+    assert_eq!(
+        contents,
+        format!("{HEADER_ROW}\nhttps://www.google.com|Google Search Engine|Search,Google\n")
+    );
+
+    let msg = last_commit_message(csv_dir.path())?;
+    assert!(
+        msg.starts_with("Edit bookmark for https://www.google.com"),
+        "Unexpected commit message: {msg}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn edit_bookmark_not_found() -> Result<()> {
+    let (_csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(&csv_path, "https://google.com", "Google", None)?;
+
+    cmd.arg("edit")
+        .arg("https://bing.com")
+        .arg("-d")
+        .arg("Bing");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("has not been bookmarked"));
+
+    Ok(())
+}
+
+#[test]
+fn edit_no_duplicate_urls() -> Result<()> {
+    let (_csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(&csv_path, "https://google.com", "Google", None)?;
+    setup_add(&csv_path, "https://bing.com", "Bing", None)?;
+
+    cmd.arg("edit")
+        .arg("https://bing.com")
+        .arg("--url")
+        .arg("https://google.com");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("has already been bookmarked"));
+
+    // The same duplicate checks as add
+    setup_cmd(&csv_path)?
+        .args(["edit", "https://bing.com", "--url", "http://GOOGLE.com/"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "http://GOOGLE.com/ has already been bookmarked as https://google.com",
+        ));
+
+    // A URL which is only a duplicate of the bookmark being edited is fine
+    setup_cmd(&csv_path)?
+        .args(["edit", "https://bing.com", "--url", "https://bing.com/"])
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn remove_exact_url() -> Result<()> {
+    let (csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(&csv_path, "https://google.com", "Google", None)?;
+    setup_add(&csv_path, "https://google.com/maps", "Google Maps", None)?;
+
+    // Only the exact URL is removed even though it would match both as a REGEX
+    cmd.arg("rm").arg("https://google.com").arg("--yes");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1 bookmark(s) removed"));
+
+    let contents = fs::read_to_string(&csv_path)?;
+    assert_eq!(
+        contents,
+        format!("{HEADER_ROW}\nhttps://google.com/maps|Google Maps|\n")
+    );
+    assert_eq!(
+        last_commit_message(csv_dir.path())?,
+        "Remove bookmark for https://google.com"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn remove_url_not_bookmarked() -> Result<()> {
+    let (_csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(&csv_path, "https://google.com/maps", "Google Maps", None)?;
+    let before = fs::read_to_string(&csv_path)?;
+
+    // Not treated as a REGEX, which would remove https://google.com/maps
+    cmd.arg("rm").arg("https://google.com").arg("--yes");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "No bookmark with URL https://google.com",
+    ));
+
+    assert_eq!(fs::read_to_string(&csv_path)?, before);
+
+    Ok(())
+}
+
+#[test]
+fn remove_regex_and_tags_with_confirmation() -> Result<()> {
+    let (csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://google.com",
+        "Google",
+        Some(vec!["Search"]),
+    )?;
+    setup_add(&csv_path, "https://bing.com", "Bing", Some(vec!["search"]))?;
+    setup_add(&csv_path, "https://github.com", "GitHub", None)?;
+
+    cmd.arg("rm").arg("g").arg("-t").arg("search");
+    assert_cmd::Command::from_std(cmd)
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 bookmark(s) removed"));
+
+    let contents = fs::read_to_string(&csv_path)?;
+    assert_eq!(
+        contents,
+        format!("{HEADER_ROW}\nhttps://github.com|GitHub|\n")
+    );
+    assert_eq!(
+        last_commit_message(csv_dir.path())?,
+        "Remove 2 bookmarks\n\nhttps://google.com\nhttps://bing.com"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn remove_not_confirmed() -> Result<()> {
+    let (_csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(&csv_path, "https://google.com", "Google", None)?;
+    let before = fs::read_to_string(&csv_path)?;
+
+    cmd.arg("rm").arg("google");
+    assert_cmd::Command::from_std(cmd)
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No bookmarks removed"));
+
+    assert_eq!(fs::read_to_string(&csv_path)?, before);
+
+    Ok(())
+}
+
+#[test]
+fn remove_no_match() -> Result<()> {
+    let (_csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(&csv_path, "https://google.com", "Google", None)?;
+
+    cmd.arg("rm").arg("bing").arg("--yes");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("No bookmarks matched"));
+
+    Ok(())
+}
+
+#[test]
+fn migrate_adds_columns() -> Result<()> {
+    let (csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://google.com",
+        "Google",
+        Some(vec!["Search"]),
+    )?;
+
+    cmd.arg("migrate");
+    cmd.assert().success().stdout(predicate::str::contains(
+        "CSV file migrated and committed to git",
+    ));
+
+    // The existing bookmark's added date is when it was first committed
+    let contents = fs::read_to_string(&csv_path)?;
+    let (header, line) = contents.split_once('\n').unwrap();
+    assert_eq!(header, "URL|DESCRIPTION|TAGS|ADDED|NOTES|ARCHIVED|#escaped");
+    assert!(
+        line.starts_with("https://google.com|Google|Search|20") && line.ends_with("Z||false\n"),
+        "Unexpected line: {line}"
+    );
+    assert_eq!(
+        last_commit_message(csv_dir.path())?,
+        "Migrate bookmarks to columns URL|DESCRIPTION|TAGS|ADDED|NOTES|ARCHIVED"
+    );
+
+    // New bookmarks get an added date and the old ones are still searchable
+    setup_add(&csv_path, "https://bing.com", "Bing", Some(vec!["Search"]))?;
+    let contents = fs::read_to_string(&csv_path)?;
+    let last_line = contents.lines().last().unwrap();
+    assert!(
+        last_line.starts_with("https://bing.com|Bing|Search|20") && last_line.ends_with("Z||false"),
+        "Unexpected line: {last_line}"
+    );
+
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.arg("search").arg("-t").arg("search");
+    test_count_matches(&mut cmd, 2)?;
+
+    // A file that isn't escaped yet but has every column doesn't need migrating
+    fs::write(
+        &csv_path,
+        "URL|DESCRIPTION|TAGS|ADDED|NOTES|ARCHIVED\nhttps://google.com|Google|Search|||false\n",
+    )?;
+    setup_cmd(&csv_path)?
+        .args(["migrate", "--no-commit"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("already has every column"));
+
+    Ok(())
+}
+
+#[test]
+fn lock_file_is_not_committed() -> Result<()> {
+    let (csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(&csv_path, "https://google.com", "Google", None)?;
+    // A lock file left by a process that was killed doesn't count as an uncommitted change
+    fs::write(csv_dir.path().join("tmp.csv.lock"), "1 (bm add)")?;
+    setup_add(&csv_path, "https://bing.com", "Bing", None)?;
+
+    // Removed once the lock is released
+    assert!(!csv_dir.path().join("tmp.csv.lock").exists());
+
+    let repo = Repository::open(csv_dir.path())?;
+    let tree = repo.head()?.peel_to_tree()?;
+    assert!(tree.get_name("tmp.csv").is_some());
+    assert!(tree.get_name("tmp.csv.lock").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn lock_file_is_removed_after_failure() -> Result<()> {
+    let (csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(&csv_path, "https://a.com", "A", None)?;
+    setup_cmd(&csv_path)?
+        .args(["add", "https://a.com", "X", "--no-commit"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "https://a.com has already been bookmarked",
+        ));
+
+    // Nothing is left saying the failed process holds the lock
+    assert!(!csv_dir.path().join("tmp.csv.lock").exists());
+
+    Ok(())
+}
+
+#[test]
+fn prompts_do_not_hold_the_lock() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(&csv_path, "https://a.com", "A", None)?;
+
+    let mut remove = setup_cmd(&csv_path)?
+        .args(["rm", "https://a.com", "--no-commit"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    // Wait until it is asking for confirmation
+    let mut stdout = remove.stdout.take().unwrap();
+    let mut output = Vec::new();
+    let mut buf = [0; 256];
+    while !String::from_utf8_lossy(&output).contains("Remove 1 bookmark(s)?") {
+        let read = stdout.read(&mut buf)?;
+        ensure!(read > 0, "rm exited before asking");
+        output.extend_from_slice(&buf[..read]);
+    }
+
+    setup_cmd(&csv_path)?
+        .env("BOOKMARK_MANAGER_LOCK_TIMEOUT", "0")
+        .args(["add", "https://b.com", "B", "--no-commit"])
+        .assert()
+        .success();
+
+    // The bookmark added in the meantime is kept
+    remove.stdin.take().unwrap().write_all(b"y\n")?;
+    ensure!(remove.wait()?.success(), "rm failed");
+    assert_eq!(
+        fs::read_to_string(&csv_path)?,
+        format!("{HEADER_ROW}\nhttps://b.com|B|\n")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn import_html() -> Result<()> {
+    let (csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(&csv_path, "https://github.com", "GitHub", None)?;
+
+    // Outside the git repo so the repo stays clean
+    let export_dir = tempdir()?;
+    let export_path = export_dir.path().join("export.html");
+    fs::write(
+        &export_path,
+        r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<H1>Bookmarks</H1>
+<DL><p>
+    <DT><H3>Search</H3>
+    <DL><p>
+        <DT><A HREF="https://google.com/?q=a|b" ADD_DATE="1626950001">Google | Search</A>
+        <DT><A HREF="https://bing.com">Bing</A>
+    </DL><p>
+    <DT><A HREF="https://github.com">GitHub</A>
+    <DT><A HREF="https://bing.com">Bing again</A>
+    <DT><A HREF="javascript:alert(1)">Bookmarklet</A>
+</DL><p>
+"#,
+    )?;
+    cmd.arg("import").arg("html").arg(&export_path);
+    cmd.assert().success().stdout(predicate::str::contains(
+        "Imported 2 bookmarks (2 already bookmarked, 1 invalid) and committed to git",
+    ));
+
+    let contents = fs::read_to_string(&csv_path)?;
+    assert_eq!(
+        contents,
+        format!(
+            "{HEADER_ROW}\nhttps://github.com|GitHub|\nhttps://google.com/?q=a\\|b|Google \\| Search|Search\nhttps://bing.com|Bing|Search\n"
+        )
+    );
+    assert_eq!(
+        last_commit_message(csv_dir.path())?,
+        "Import 2 bookmarks from export.html"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn export_html() -> Result<()> {
+    let (_csv_dir, csv_path, mut cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://google.com",
+        "Google & co",
+        Some(vec!["Search"]),
+    )?;
+    setup_add(&csv_path, "https://bing.com", "Bing", Some(vec!["search"]))?;
+    setup_add(&csv_path, "https://github.com", "GitHub", None)?;
+
+    cmd.arg("export")
+        .arg("html")
+        .arg("--folders")
+        .arg("first-tag");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "<!DOCTYPE NETSCAPE-Bookmark-file-1>",
+        ))
+        .stdout(predicate::str::contains(
+            "    <DT><H3>Search</H3>
+    <DL><p>
+        <DT><A HREF=\"https://google.com\">Google &amp; co</A>
+        <DT><A HREF=\"https://bing.com\">Bing</A>
+    </DL><p>
+    <DT><A HREF=\"https://github.com\">GitHub</A>
+</DL><p>",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn search_machine_readable_formats() -> Result<()> {
+    let (_csv_dir, csv_path, _cmd) = setup()?;
+
+    setup_add(
+        &csv_path,
+        "https://google.com",
+        "Google Search",
+        Some(vec!["Search"]),
+    )?;
+    setup_add(
+        &csv_path,
+        "https://github.com",
+        "GitHub",
+        Some(vec!["code,git"]),
+    )?;
+
+    setup_cmd(&csv_path)?
+        .args(["s", "google", "--format", "jsonl", "--spans"])
+        .assert()
+        .success()
+        .stdout(
+            "{\"url\":\"https://google.com\",\"description\":\"Google Search\",\"tags\":[\"Search\"],\
+            \"matches\":{\"url\":[[8,14]],\"description\":[[0,6]]}}\n",
+        );
+
+    setup_cmd(&csv_path)?
+        .args(["s", "git", "--format", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"url\": \"https://github.com\""))
+        .stdout(predicate::str::contains("matches").not());
+
+    setup_cmd(&csv_path)?
+        .args(["s", "-t", "search", "--format", "tsv"])
+        .assert()
+        .success()
+        .stdout("https://google.com\tGoogle Search\tSearch\n");
+
+    // A comma in a tag is escaped so the tags can be split
+    setup_cmd(&csv_path)?
+        .args(["s", "github", "--format", "tsv"])
+        .assert()
+        .success()
+        .stdout("https://github.com\tGitHub\tcode\\,git\n");
+
+    setup_cmd(&csv_path)?
+        .args(["s", "google", "--format", "tsv", "--spans"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--spans only works with --format json or jsonl",
+        ));
+
+    setup_cmd(&csv_path)?
+        .args(["s", "-t", "search", "--format", "csv"])
+        .assert()
+        .success()
+        .stdout("https://google.com|Google Search|Search\n");
+
+    Ok(())
+}
+
+#[test]
+fn open_single_match() -> Result<()> {
+    let (csv_dir, csv_path, mut cmd) = setup()?;
+    setup_add(&csv_path, "https://google.com", "Google", None)?;
+    setup_add(&csv_path, "https://bing.com", "Bing", None)?;
+    // Created after adding since it isn't committed
+    let (opener, opened) = fake_browser(csv_dir.path())?;
+
+    cmd.arg("open").arg("goo").arg("--opener").arg(&opener);
+    cmd.assert().success();
+    assert_eq!(wait_for_opened(&opened, 1)?, vec!["https://google.com"]);
+
+    Ok(())
+}
+
+#[test]
+fn open_pick_from_multiple_matches() -> Result<()> {
+    let (csv_dir, csv_path, _cmd) = setup()?;
+    setup_add(
+        &csv_path,
+        "https://google.com",
+        "Google",
+        Some(vec!["Search"]),
+    )?;
+    setup_add(&csv_path, "https://bing.com", "Bing", Some(vec!["search"]))?;
+    let (opener, opened) = fake_browser(csv_dir.path())?;
+
+    let mut cmd = setup_cmd(&csv_path)?;
+    cmd.env("BOOKMARK_MANAGER_OPENER", &opener)
+        .arg("open")
+        .arg("-t")
+        .arg("search");
+    assert_cmd::Command::from_std(cmd)
+        .write_stdin("2\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1) https://google.com"))
+        .stdout(predicate::str::contains("2) https://bing.com"));
+    assert_eq!(wait_for_opened(&opened, 1)?, vec!["https://bing.com"]);
+
+    fs::remove_file(&opened)?;
+    setup_cmd(&csv_path)?
+        .env("BOOKMARK_MANAGER_OPENER", &opener)
+        .args(["open", "-t", "search", "--all"])
+        .assert()
+        .success();
+    // The browser is launched without waiting for it, so they can be opened in any order
+    let mut urls = wait_for_opened(&opened, 2)?;
+    urls.sort();
+    assert_eq!(urls, vec!["https://bing.com", "https://google.com"]);
+
+    Ok(())
+}
+
+#[test]
+fn open_reports_opener_failure() -> Result<()> {
+    let (_csv_dir, csv_path, mut cmd) = setup()?;
+    setup_add(&csv_path, "https://google.com", "Google", None)?;
+
+    // ls fails since there is no file named after the URL
+    cmd.args(["open", "goo", "--opener", "ls"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "ls could not open https://google.com (exit status:",
+        ))
+        .stderr(predicate::str::contains("No such file or directory"));
+
+    Ok(())
+}
+
+/// A script that stands in for the browser by appending the URL it was given to a file.
+/// Returns the script's path and the file.
+fn fake_browser(dir: &Path) -> Result<(String, PathBuf)> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let opened = dir.join("opened.txt");
+    let script = dir.join("browser.sh");
+    fs::write(
+        &script,
+        format!("#!/bin/sh\necho \"$1\" >> \"{}\"\n", opened.display()),
+    )?;
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+
+    Ok((script.display().to_string(), opened))
+}
+
+/// The URLs opened by the fake browser, once it has opened `count` of them
+fn wait_for_opened(opened: &Path, count: usize) -> Result<Vec<String>> {
+    for _ in 0..100 {
+        let urls = fs::read_to_string(opened)
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect::<Vec<String>>();
+        if urls.len() >= count {
+            return Ok(urls);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    anyhow::bail!("The browser wasn't opened {count} time(s)")
+}
+
+/// Serve the paths used by the linkcheck and page title tests on a local port. Returns the URL of the server (without a trailing /).
+fn serve_pages() -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let server = format!("http://{}", listener.local_addr()?);
+
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            // Only the request line is needed, but read the whole request so the client sees a clean close
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => request.extend_from_slice(&buf[..read]),
+                }
+            }
+            let request = String::from_utf8_lossy(&request);
+            let mut parts = request.split_whitespace();
+            let (method, path) = (
+                parts.next().unwrap_or_default(),
+                parts.next().unwrap_or_default(),
+            );
+            // Ignore the query so the same page can be bookmarked more than once
+            let path = path.split('?').next().unwrap_or_default();
+
+            let body = match (method, path) {
+                ("GET", "/titled") => {
+                    "<html><head><title>Rust | Docs &amp;\n  more</title></head></html>"
+                }
+                _ => "",
+            };
+            let (status, location) = match (method, path) {
+                // Some servers drop HEAD requests
+                ("HEAD", "/no-head") => continue,
+                (_, "/ok" | "/new" | "/titled" | "/no-head") => ("200 OK", None),
+                (_, "/moved") => ("301 Moved Permanently", Some("/new")),
+                (_, "/temporary") => ("302 Found", Some("/ok")),
+                (_, "/duplicate") => ("308 Permanent Redirect", Some("/ok")),
+                ("HEAD", "/get-only") => ("405 Method Not Allowed", None),
+                (_, "/forbidden") => ("403 Forbidden", None),
+                ("GET", "/get-only") => ("200 OK", None),
+                _ => ("404 Not Found", None),
+            };
+            let location = location
+                .map(|location| format!("Location: {location}\r\n"))
+                .unwrap_or_default();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {status}\r\n{location}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+
+    Ok(server)
+}
+
 /// Set up the test environment with a temporary CSV file.
 /// To populate the CSV with contents, use "add" command.
 ///
@@ -583,6 +2024,13 @@ fn setup_add(csv_path: &Path, url: &str, description: &str, tags: Option<Vec<&st
     Ok(())
 }
 
+/// The message of the commit HEAD points to
+fn last_commit_message(git_repo_path: &Path) -> Result<String> {
+    let repo = Repository::open(git_repo_path)?;
+    let commit = repo.head()?.peel_to_commit()?;
+    Ok(String::from(commit.message().unwrap_or_default()))
+}
+
 fn test_count_matches(cmd: &mut Command, expected_num_matches: usize) -> Result<()> {
     let assert = cmd.assert().success();
 
@@ -9,16 +9,41 @@ function _tab_complete_bm()
   words=""
   case "${prev}" in
     --tag | -t)
-      words=$(bm tags --machine)
+      words=$(_bm_tag_level "$latest")
       ;;
     *)
       ;;
   esac
   # shellcheck disable=SC2207
   COMPREPLY=( $(compgen -W "$words" -- "$latest") )
+  # Don't add a space after a level of the hierarchy (e.g. lang/) so the next level can be completed
+  if [[ ${#COMPREPLY[@]} -eq 1 ]] && [[ ${COMPREPLY[0]} == */ ]]; then
+    compopt -o nospace
+  fi
   return 0
 }
 
+# Tags containing / are a hierarchy (e.g. lang/rust/async) so only complete the next level
+# e.g. "lang/r" completes to lang/rust/ if there are tags below lang/rust
+function _bm_tag_level()
+{
+  # Everything up to and including the last / in what has been typed so far
+  local prefix=""
+  if [[ $1 == */* ]]; then
+    prefix="${1%/*}/"
+  fi
+  bm tags --machine | awk -v prefix="$prefix" '
+    index($0, prefix) == 1 {
+      rest = substr($0, length(prefix) + 1)
+      slash = index(rest, "/")
+      if (slash > 0) {
+        print prefix substr(rest, 1, slash)
+      } else {
+        print $0
+      }
+    }' | sort -u
+}
+
 # Add completions using fzf if it is installed
 if type fzf &>/dev/null && type __fzf_defc &>/dev/null; then
   # Allow `bm s -t **<tab>` to complete the tag with fzf